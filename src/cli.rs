@@ -0,0 +1,90 @@
+// Copyright (C) 2022  ThatsNoMoon
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of version 3 of the GNU Affero General Public License as published
+// by the Free Software Foundation.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU Affero General Public License
+// for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Headless commands, which don't open a window and so work without a GPU.
+
+use std::{fs, path::Path};
+
+use crate::map::{Background, ExportFormat, ExportOptions, Map};
+
+const USAGE: &str = "\
+Usage: metro-draw [export <project> <output> [options]]
+
+Exports the project to an SVG or PNG image, chosen by the output file's
+extension. Without arguments, the editor is opened instead.
+
+Options:
+    --grid           Draw the grid behind the map
+    --transparent    Leave the background transparent
+    --scale <n>      Pixels per map unit for PNG exports (default: 2)
+    --dpi <n>        Set the scale from a resolution, assuming 96 DPI is 1:1";
+
+pub(crate) fn run(args: &[String]) -> Result<(), String> {
+	match args {
+		[command, input, output, options @ ..] if command == "export" => {
+			export(Path::new(input), Path::new(output), options)
+		}
+		_ => Err(USAGE.to_owned()),
+	}
+}
+
+fn export(input: &Path, output: &Path, args: &[String]) -> Result<(), String> {
+	let format = match output.extension().and_then(|e| e.to_str()) {
+		Some(e) if e.eq_ignore_ascii_case("svg") => ExportFormat::Svg,
+		Some(e) if e.eq_ignore_ascii_case("png") => ExportFormat::Png,
+		_ => {
+			return Err(format!(
+				"Can't tell what format to export {} as; use a .svg or .png \
+				 extension",
+				output.display()
+			))
+		}
+	};
+
+	let mut options = ExportOptions::default();
+
+	let mut args = args.iter();
+	while let Some(arg) = args.next() {
+		match arg.as_str() {
+			"--grid" => options.grid = true,
+			"--transparent" => options.background = Background::Transparent,
+			"--scale" | "--dpi" => {
+				let value = args
+					.next()
+					.and_then(|v| v.parse::<f32>().ok())
+					.filter(|v| v.is_finite() && *v > 0.0)
+					.ok_or_else(|| {
+						format!("{arg} needs a positive number\n\n{USAGE}")
+					})?;
+
+				options.scale =
+					if arg == "--dpi" { value / 96.0 } else { value };
+			}
+			_ => return Err(format!("Unknown option {arg}\n\n{USAGE}")),
+		}
+	}
+
+	let source = fs::read_to_string(input)
+		.map_err(|e| format!("Failed to read {}: {e}", input.display()))?;
+
+	let map = Map::deserialize(&source)
+		.map_err(|e| format!("Failed to open {}: {e}", input.display()))?;
+
+	let contents = map
+		.export(format, &options)
+		.map_err(|e| format!("Failed to export map: {e}"))?;
+
+	fs::write(output, contents)
+		.map_err(|e| format!("Failed to write {}: {e}", output.display()))
+}
@@ -16,6 +16,13 @@ use iced::Color;
 
 pub(crate) trait ColorExt {
 	fn from_rgb32(rgb: u32) -> Self;
+
+	/// Parses a six digit hex color, with or without a leading `#`.
+	fn from_hex(hex: &str) -> Option<Self>
+	where
+		Self: Sized;
+
+	fn to_rgb32(self) -> u32;
 }
 
 impl ColorExt for Color {
@@ -28,4 +35,19 @@ impl ColorExt for Color {
 
 		Self::from_rgb8(r, g, b)
 	}
+
+	fn from_hex(hex: &str) -> Option<Self> {
+		let hex = hex.strip_prefix('#').unwrap_or(hex);
+		if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+			return None;
+		}
+
+		u32::from_str_radix(hex, 16).ok().map(Self::from_rgb32)
+	}
+
+	fn to_rgb32(self) -> u32 {
+		let channel = |c: f32| (c * 255.0).round() as u32 & 0xFF;
+
+		channel(self.r) << 16 | channel(self.g) << 8 | channel(self.b)
+	}
 }
@@ -0,0 +1,76 @@
+// Copyright (C) 2022  ThatsNoMoon
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of version 3 of the GNU Affero General Public License as published
+// by the Free Software Foundation.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU Affero General Public License
+// for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::{fs, io, path::PathBuf};
+
+use rfd::AsyncFileDialog;
+
+use crate::map::{Map, ProjectError, EXTENSION};
+
+/// Asks the user for a project to open, then reads it.
+///
+/// Returns `None` if the user cancelled the dialog.
+pub(crate) async fn open() -> Option<Result<(PathBuf, Box<Map>), ProjectError>>
+{
+	let path = AsyncFileDialog::new()
+		.add_filter("MetroDraw project", &[EXTENSION])
+		.pick_file()
+		.await?
+		.path()
+		.to_owned();
+
+	let result = fs::read_to_string(&path)
+		.map_err(ProjectError::from)
+		.and_then(|source| Map::deserialize(&source));
+
+	Some(result.map(|map| (path, Box::new(map))))
+}
+
+/// Asks the user where to save a project.
+///
+/// Returns `None` if the user cancelled the dialog.
+pub(crate) async fn pick_save_path() -> Option<PathBuf> {
+	pick_path("MetroDraw project", EXTENSION).await
+}
+
+/// Asks the user where to save a file of the given type.
+///
+/// Returns `None` if the user cancelled the dialog.
+pub(crate) async fn pick_path(
+	description: &'static str,
+	extension: &'static str,
+) -> Option<PathBuf> {
+	let mut path = AsyncFileDialog::new()
+		.add_filter(description, &[extension])
+		.set_file_name(format!("map.{extension}"))
+		.save_file()
+		.await?
+		.path()
+		.to_owned();
+
+	if path.extension().is_none() {
+		path.set_extension(extension);
+	}
+
+	Some(path)
+}
+
+pub(crate) async fn write<E: From<io::Error>>(
+	path: PathBuf,
+	contents: impl AsRef<[u8]>,
+) -> Result<PathBuf, E> {
+	fs::write(&path, contents)?;
+
+	Ok(path)
+}
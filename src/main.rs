@@ -14,31 +14,139 @@
 
 #![allow(dead_code)]
 
+mod cli;
 mod color;
+mod file;
 mod map;
+mod panel;
+
+use std::{env, path::PathBuf, process};
 
 use iced::{
-	executor,
-	pure::{Application, Element},
-	Command, Length, Settings,
+	clipboard, executor,
+	pure::{
+		button, checkbox, column, container, pick_list, row, text, text_input,
+		Application, Element,
+	},
+	Alignment, Color, Command, Length, Point, Settings, Vector,
 };
 
-use crate::map::{LineIndex, Map, Station, StationIndex};
+use crate::{
+	color::ColorExt,
+	map::{
+		Anchor, Background, Edit, ExportError, ExportFormat, ExportOptions,
+		History, InterpolationDirection, LineIndex, Map, Marker, ProjectError,
+		Redraw, Revision, SegmentId, Selection, Series, StationId,
+		StationIndex,
+	},
+	panel::LinePanel,
+};
 
 struct State {
 	map: Map,
+	history: History,
 	edit_mode: EditMode,
 	selected_line: LineIndex,
+	/// Where the map was last opened from or saved to.
+	path: Option<PathBuf>,
+	/// Marked with whatever each update changed, so the map view knows what
+	/// to draw again.
+	revision: Revision,
+	export_options: ExportOptions,
+	/// The station being renamed, if any.
+	editing: Option<StationEditor>,
+	line_panel: LinePanel,
+	selection: Selection,
+}
+
+#[derive(Debug, Clone)]
+struct StationEditor {
+	station: StationId,
+	name: String,
 }
 
 #[derive(Debug, Clone)]
 enum Message {
-	AddStation(Station),
+	AddStation(Point),
+	/// Adds a station on a segment, as close to the point as it can be.
+	SplitSegment(SegmentId, Point),
 	AddSegment(StationIndex, StationIndex),
 	RemoveStation(StationIndex),
+	RemoveSegment(SegmentId),
+	RemoveTransfer(StationId, StationId),
 	SwitchLine(LineIndex),
 	ClearMap,
 	SwitchEditMode(EditMode),
+	Undo,
+	Redo,
+	/// A drag on the map started; edits until it ends are undone together.
+	DragStarted,
+	DragEnded,
+	Open,
+	Opened(Option<Result<(PathBuf, Box<Map>), ProjectError>>),
+	Save,
+	SaveAs,
+	SavePathPicked(Option<PathBuf>),
+	Saved(Result<PathBuf, ProjectError>),
+	Export(ExportFormat),
+	ExportPathPicked(ExportFormat, Option<PathBuf>),
+	Exported(Result<PathBuf, ExportError>),
+	ToggleExportGrid,
+	ToggleExportBackground,
+	EditStation(StationIndex),
+	StationNameChanged(String),
+	LabelAnchorPicked(Anchor),
+	LabelPinned(bool),
+	StationMarkerPicked(Marker),
+	StationAccessible(bool),
+	StationEdited,
+	MoveLabel(StationIndex, Vector),
+	PlaceLabels,
+	AddLine,
+	/// Removes a line, moving its segments to another line if one is given.
+	RemoveLine(LineIndex, Option<LineIndex>),
+	MoveLine(LineIndex, LineIndex),
+	LineNameChanged(LineIndex, String),
+	LineCodeChanged(LineIndex, String),
+	LineColorChanged(LineIndex, Color),
+	LineHexChanged(LineIndex, String),
+	/// Turns every service of a line around.
+	ReverseServices(LineIndex),
+	ReassignPicked(Option<LineIndex>),
+	Select(Selection),
+	/// Moves every selected station.
+	MoveSelection(Vector),
+	DeleteSelection,
+	/// Moves every selected segment to the selected line.
+	RecolorSelection,
+	/// Selects the rest of the lines the selected segments are on, up to
+	/// where they end or branch.
+	SelectRuns,
+	/// Joins the selected stations into one complex with transfers.
+	JoinStations,
+	/// Removes the transfers to and from the selected stations.
+	SeparateStations,
+	Copy,
+	/// Copies the selection, then deletes it.
+	Cut,
+	/// Pastes the map fragment on the clipboard at the given point.
+	Paste(Point),
+	Pasted(Point, Option<String>),
+	/// Sets which way every selected segment bends.
+	SetBendDirection(InterpolationDirection),
+	/// Moves each selected segment on to the next way it can bend.
+	CycleBendDirection,
+	/// Moves the bend in each selected segment to its other end.
+	FlipBend,
+	/// Draws each selected segment as a curve, or straightens it.
+	SetCurve(bool),
+	SetTurnRadius(f32),
+	/// Gives the selected segments their own turn radius, or the map's.
+	SetSegmentTurnRadius(Option<f32>),
+	/// Adds a waypoint to a segment, wherever along it is closest.
+	InsertWaypoint(SegmentId, Point),
+	MoveWaypoint(SegmentId, usize, Point),
+	RemoveWaypoint(SegmentId, usize),
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -58,52 +166,514 @@ impl Application for State {
 		(
 			State {
 				map: Map::default(),
+				history: History::default(),
 				edit_mode: EditMode::Station,
 				selected_line: 0,
+				path: None,
+				revision: Revision::default(),
+				export_options: ExportOptions::default(),
+				editing: None,
+				line_panel: LinePanel::default(),
+				selection: Selection::default(),
 			},
 			Command::none(),
 		)
 	}
 
 	fn title(&self) -> String {
-		"MetroDraw".to_owned()
+		match self.path.as_ref().and_then(|p| p.file_name()) {
+			Some(name) => format!("{} - MetroDraw", name.to_string_lossy()),
+			None => "MetroDraw".to_owned(),
+		}
 	}
 
 	fn update(&mut self, message: Message) -> Command<Message> {
+		let selection = self.selection.clone();
+
 		match message {
-			Message::AddStation(station) => {
-				self.map.add_station(station);
+			Message::AddStation(position) => {
+				let undo = self.map.add_station(position);
+				self.record(undo);
+			}
+			Message::SplitSegment(segment, at) => {
+				let undo = self.map.split_segment(segment, at);
+				self.record(undo);
 			}
 			Message::AddSegment(start, end) => {
-				self.map.add_segment(self.selected_line, start, end);
+				let undo = self.map.add_segment(self.selected_line, start, end);
+				self.record(undo);
 			}
 			Message::RemoveStation(station) => {
-				self.map.remove_station(station);
+				let undo = self.map.remove_station(station);
+				self.record(undo);
+			}
+			Message::RemoveSegment(segment) => {
+				let undo = self.map.remove_segment(segment);
+				self.record(undo);
+			}
+			Message::RemoveTransfer(a, b) => {
+				let undo = self.map.remove_transfer(a, b);
+				self.record(undo);
 			}
 			Message::SwitchLine(line) => {
 				self.selected_line = line;
+				self.line_panel = LinePanel::default();
 			}
 			Message::ClearMap => {
-				self.map.clear();
+				let undo = self.map.clear();
+				self.record(undo);
 			}
 			Message::SwitchEditMode(mode) => {
 				self.edit_mode = mode;
 			}
+			Message::Undo => {
+				if let Some(redo) = self.history.undo(&mut self.map) {
+					self.revision.mark(redo.redraw(&self.map));
+				}
+				self.line_panel = LinePanel::default();
+			}
+			Message::Redo => {
+				if let Some(undo) = self.history.redo(&mut self.map) {
+					self.revision.mark(undo.redraw(&self.map));
+				}
+				self.line_panel = LinePanel::default();
+			}
+			Message::DragStarted => {
+				self.close_editor();
+				self.history.begin_group();
+			}
+			Message::DragEnded => self.history.end_group(),
+			Message::Open => {
+				return Command::perform(file::open(), Message::Opened);
+			}
+			Message::Opened(None) => (),
+			Message::Opened(Some(Ok((path, map)))) => {
+				self.editing = None;
+				self.map = *map;
+				self.revision.mark(Redraw::ALL);
+				self.history.clear();
+				self.path = Some(path);
+				self.selected_line = 0;
+				self.line_panel = LinePanel::default();
+				self.selection = Selection::default();
+			}
+			Message::Opened(Some(Err(e))) => {
+				eprintln!("Failed to open project: {e}");
+			}
+			Message::Save => match self.path.clone() {
+				Some(path) => return self.save(path),
+				None => return self.update(Message::SaveAs),
+			},
+			Message::SaveAs => {
+				return Command::perform(
+					file::pick_save_path(),
+					Message::SavePathPicked,
+				);
+			}
+			Message::SavePathPicked(None) => (),
+			Message::SavePathPicked(Some(path)) => return self.save(path),
+			Message::Saved(Ok(path)) => {
+				self.path = Some(path);
+			}
+			Message::Saved(Err(e)) => {
+				eprintln!("Failed to save project: {e}");
+			}
+			Message::Export(format) => {
+				return Command::perform(
+					file::pick_path(format.description(), format.extension()),
+					move |path| Message::ExportPathPicked(format, path),
+				);
+			}
+			Message::ExportPathPicked(_, None) => (),
+			Message::ExportPathPicked(format, Some(path)) => {
+				match self.map.export(format, &self.export_options) {
+					Ok(contents) => {
+						return Command::perform(
+							file::write(path, contents),
+							Message::Exported,
+						);
+					}
+					Err(e) => eprintln!("Failed to export map: {e}"),
+				}
+			}
+			Message::Exported(Ok(_)) => (),
+			Message::Exported(Err(e)) => {
+				eprintln!("Failed to export map: {e}");
+			}
+			Message::ToggleExportGrid => {
+				self.export_options.grid = !self.export_options.grid;
+			}
+			Message::ToggleExportBackground => {
+				self.export_options.background =
+					match self.export_options.background {
+						Background::Theme => Background::Transparent,
+						Background::Transparent => Background::Theme,
+					};
+			}
+			Message::EditStation(index) => {
+				self.close_editor();
+
+				// Everything changed while the editor is open is undone at
+				// once, rather than a letter at a time
+				self.history.begin_group();
+
+				let station = self.map.station(index);
+				self.editing = Some(StationEditor {
+					station: station.id(),
+					name: station.name().to_owned(),
+				});
+			}
+			Message::StationNameChanged(name) => {
+				if let Some(editor) = &mut self.editing {
+					editor.name = name.clone();
+
+					if self.map.find_station(editor.station).is_some() {
+						let undo =
+							self.map.rename_station(editor.station, name);
+						self.record(undo);
+					}
+				}
+			}
+			Message::LabelAnchorPicked(anchor) => {
+				if let Some(editor) = &self.editing {
+					if self.map.find_station(editor.station).is_some() {
+						let undo =
+							self.map.set_label_anchor(editor.station, anchor);
+						self.record(undo);
+					}
+				}
+			}
+			Message::LabelPinned(pinned) => {
+				if let Some(editor) = &self.editing {
+					if self.map.find_station(editor.station).is_some() {
+						let undo = self.map.pin_label(editor.station, pinned);
+						self.record(undo);
+					}
+				}
+			}
+			Message::StationMarkerPicked(marker) => {
+				if let Some(editor) = &self.editing {
+					if self.map.find_station(editor.station).is_some() {
+						let undo =
+							self.map.set_station_marker(editor.station, marker);
+						self.record(undo);
+					}
+				}
+			}
+			Message::StationAccessible(accessible) => {
+				if let Some(editor) = &self.editing {
+					if self.map.find_station(editor.station).is_some() {
+						let undo = self
+							.map
+							.set_station_accessible(editor.station, accessible);
+						self.record(undo);
+					}
+				}
+			}
+			Message::StationEdited => self.close_editor(),
+			Message::MoveLabel(station, offset) => {
+				let undo = self.map.move_label(station, offset);
+				self.record(undo);
+			}
+			Message::PlaceLabels => {
+				let undo = self.map.place_labels();
+				self.record(undo);
+			}
+			Message::AddLine => {
+				let undo = self.map.add_line();
+				self.record(undo);
+				self.selected_line = self.map.lines().len() as LineIndex - 1;
+				self.line_panel = LinePanel::default();
+			}
+			Message::RemoveLine(line, reassign) => {
+				let undo = self.map.remove_line(line, reassign);
+				self.record(undo);
+				if self.selected_line > line {
+					self.selected_line -= 1;
+				}
+				self.line_panel = LinePanel::default();
+			}
+			Message::MoveLine(from, to) => {
+				let undo = self.map.move_line(from, to);
+				self.record(undo);
+				self.selected_line = to;
+				self.line_panel = LinePanel::default();
+			}
+			Message::LineNameChanged(line, name) => {
+				let undo = self.map.rename_line(line, name);
+				self.record_series(undo, Series::Name(line));
+			}
+			Message::LineCodeChanged(line, code) => {
+				let undo = self.map.set_line_code(line, code);
+				self.record_series(undo, Series::Code(line));
+			}
+			Message::LineColorChanged(line, color) => {
+				let undo = self.map.recolor_line(line, color);
+				self.record_series(undo, Series::Color(line));
+				self.line_panel.hex = None;
+			}
+			Message::LineHexChanged(line, hex) => {
+				if let Some(color) = Color::from_hex(&hex) {
+					let undo = self.map.recolor_line(line, color);
+					self.record_series(undo, Series::Color(line));
+				}
+				self.line_panel.hex = Some(hex);
+			}
+			Message::ReverseServices(line) => {
+				let undo = self.map.reverse_services(line);
+				self.record(undo);
+			}
+			Message::ReassignPicked(line) => {
+				self.line_panel.reassign = line;
+			}
+			Message::Select(selection) => {
+				self.selection = selection;
+			}
+			Message::MoveSelection(by) => {
+				let undo = self.map.move_selection(&self.selection, by);
+				self.record(undo);
+			}
+			Message::DeleteSelection => {
+				let undo = self.map.remove_selection(&self.selection);
+				self.record(undo);
+			}
+			Message::RecolorSelection => {
+				let undo = self
+					.map
+					.recolor_selection(&self.selection, self.selected_line);
+				self.record(undo);
+			}
+			Message::SelectRuns => {
+				self.selection = self.map.runs(&self.selection);
+			}
+			Message::JoinStations => {
+				let undo = self.map.join_stations(&self.selection);
+				self.record(undo);
+			}
+			Message::SeparateStations => {
+				let undo = self.map.separate_stations(&self.selection);
+				self.record(undo);
+			}
+			Message::SetBendDirection(direction) => {
+				let undo =
+					self.map.interpolate_selection(&self.selection, |i| {
+						i.with_direction(direction)
+					});
+				self.record(undo);
+			}
+			Message::CycleBendDirection => {
+				let undo =
+					self.map.interpolate_selection(&self.selection, |i| {
+						let next = i.direction().map_or(
+							InterpolationDirection::Auto,
+							InterpolationDirection::next,
+						);
+						i.with_direction(next)
+					});
+				self.record(undo);
+			}
+			Message::FlipBend => {
+				let undo = self
+					.map
+					.interpolate_selection(&self.selection, |i| i.flipped());
+				self.record(undo);
+			}
+			Message::SetCurve(curve) => {
+				let undo =
+					self.map.interpolate_selection(&self.selection, |i| {
+						i.with_curve(curve)
+					});
+				self.record(undo);
+			}
+			Message::SetTurnRadius(radius) => {
+				let undo = self.map.set_turn_radius(radius);
+				self.record_series(undo, Series::TurnRadius);
+			}
+			Message::SetSegmentTurnRadius(radius) => {
+				let undo =
+					self.map.set_selection_turn_radius(&self.selection, radius);
+				match radius {
+					Some(_) => {
+						self.record_series(undo, Series::SegmentTurnRadius)
+					}
+					None => self.record(undo),
+				}
+			}
+			Message::InsertWaypoint(segment, at) => {
+				let undo = self.map.insert_waypoint(segment, at);
+				self.record(undo);
+			}
+			Message::MoveWaypoint(segment, waypoint, to) => {
+				let undo = self.map.move_waypoint(segment, waypoint, to);
+				self.record(undo);
+			}
+			Message::RemoveWaypoint(segment, waypoint) => {
+				let undo = self.map.remove_waypoint(segment, waypoint);
+				self.record(undo);
+			}
+			Message::Copy => return self.copy(),
+			Message::Cut => {
+				let copy = self.copy();
+				let undo = self.map.remove_selection(&self.selection);
+				self.record(undo);
+				self.selection.retain(&self.map);
+
+				return copy;
+			}
+			Message::Paste(at) => {
+				return clipboard::read(move |contents| {
+					Message::Pasted(at, contents)
+				});
+			}
+			Message::Pasted(_, None) => (),
+			Message::Pasted(at, Some(contents)) => {
+				match self.map.paste(&contents, at) {
+					Ok((undo, pasted)) => {
+						self.record(undo);
+						self.selection = pasted;
+					}
+					Err(e) => eprintln!("Failed to paste: {e}"),
+				}
+			}
 		}
 
+		// Anything selected might have been removed
+		self.selection.retain(&self.map);
+		if self.selection != selection {
+			self.revision.mark(Redraw::SELECTION);
+		}
+
+		// Undoing or deleting can leave fewer lines than there were
+		let last = self.map.lines().len() as LineIndex - 1;
+		self.selected_line = self.selected_line.min(last);
+
 		Command::none()
 	}
 
 	fn view(&self) -> Element<'_, Message> {
-		self.map
-			.view(self.edit_mode, self.selected_line)
-			.width(Length::Fill)
-			.height(Length::Fill)
+		let map = self.map.view(
+			&self.selection,
+			self.edit_mode,
+			self.selected_line,
+			self.revision,
+			self.editing.is_some(),
+		);
+
+		let editor = self.editing.as_ref().and_then(|editor| {
+			let index = self.map.find_station(editor.station)?;
+			let station = self.map.station(index);
+			let label = station.label();
+
+			let editor = row()
+				.spacing(10)
+				.align_items(Alignment::Center)
+				.push(text("Name"))
+				.push(
+					text_input(
+						"Station name",
+						&editor.name,
+						Message::StationNameChanged,
+					)
+					.padding(5)
+					.on_submit(Message::StationEdited),
+				)
+				.push(text("Label"))
+				.push(pick_list(
+					&Anchor::ALL[..],
+					Some(label.anchor()),
+					Message::LabelAnchorPicked,
+				))
+				.push(checkbox("Pinned", label.pinned(), Message::LabelPinned))
+				.push(text("Marker"))
+				.push(pick_list(
+					&Marker::ALL[..],
+					Some(station.marker()),
+					Message::StationMarkerPicked,
+				))
+				.push(checkbox(
+					"Step-free",
+					station.accessible(),
+					Message::StationAccessible,
+				))
+				.push(button("Done").on_press(Message::StationEdited));
+
+			Some(container(editor).padding(10).width(Length::Fill))
+		});
+
+		let map = match editor {
+			Some(editor) => column().push(map).push(editor).into(),
+			None => map,
+		};
+
+		row()
+			.push(map)
+			.push(panel::view(
+				&self.map,
+				self.selected_line,
+				&self.line_panel,
+				&self.selection,
+			))
 			.into()
 	}
 }
 
+impl State {
+	/// Records the edit that undoes a change just made, and has the map view
+	/// draw again whatever it changed.
+	fn record(&mut self, undo: Edit) {
+		self.revision.mark(undo.redraw(&self.map));
+		self.history.record(undo);
+	}
+
+	/// Records the edit that undoes a change in a series, like
+	/// [`State::record`].
+	fn record_series(&mut self, undo: Edit, series: Series) {
+		self.revision.mark(undo.redraw(&self.map));
+		self.history.record_series(undo, series);
+	}
+
+	/// Closes the station editor, if it's open.
+	fn close_editor(&mut self) {
+		if self.editing.take().is_some() {
+			self.history.end_group();
+		}
+	}
+
+	/// Copies the selection to the clipboard.
+	fn copy(&self) -> Command<Message> {
+		match self.map.copy(&self.selection) {
+			Ok(contents) => clipboard::write(contents),
+			Err(e) => {
+				eprintln!("Failed to copy: {e}");
+				Command::none()
+			}
+		}
+	}
+
+	fn save(&self, path: PathBuf) -> Command<Message> {
+		match self.map.serialize() {
+			Ok(contents) => {
+				Command::perform(file::write(path, contents), Message::Saved)
+			}
+			Err(e) => {
+				eprintln!("Failed to save project: {e}");
+				Command::none()
+			}
+		}
+	}
+}
+
 fn main() -> iced::Result {
+	let args: Vec<String> = env::args().skip(1).collect();
+	if !args.is_empty() {
+		if let Err(e) = cli::run(&args) {
+			eprintln!("{e}");
+			process::exit(1);
+		}
+
+		return Ok(());
+	}
+
 	State::run(Settings {
 		antialiasing: true,
 		..Settings::default()
@@ -0,0 +1,511 @@
+// Copyright (C) 2022  ThatsNoMoon
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of version 3 of the GNU Affero General Public License as published
+// by the Free Software Foundation.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU Affero General Public License
+// for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::{cmp::Ordering, collections::HashMap};
+
+use iced::{Point, Vector};
+use ordered_float::NotNan;
+use petgraph::graph::EdgeIndex;
+
+use super::{
+	geometry::{interpolate_auto, magnitude},
+	render::LINE_WIDTH,
+	Bend, Index, Interpolation, Map, StationIndex,
+};
+
+/// Segments that take the same path between the same stations, which are
+/// drawn side by side instead of on top of each other.
+#[derive(Debug, Clone)]
+pub(super) struct Bundle {
+	/// The station offsets are measured from; segments running the other way
+	/// have their offsets reversed.
+	pub(super) start: StationIndex,
+	pub(super) end: StationIndex,
+	/// The points the path between the stations goes through, from the
+	/// start to the end.
+	pub(super) path: Vec<Point>,
+	/// Whether the path is a curve through its points, rather than straight
+	/// lines between them.
+	pub(super) curve: bool,
+	/// From left to right, looking from the start towards the end.
+	pub(super) segments: Vec<EdgeIndex<Index>>,
+}
+
+/// Every bundle on the map, along with which bundle each segment is in.
+#[derive(Debug, Clone, Default)]
+pub(super) struct Bundles {
+	bundles: Vec<Bundle>,
+	bundle_of: HashMap<EdgeIndex<Index>, usize>,
+}
+
+impl Bundles {
+	pub(super) fn iter(&self) -> impl Iterator<Item = &Bundle> + '_ {
+		self.bundles.iter()
+	}
+
+	/// The bundle a segment is in, and where it is in the bundle. Bundles are
+	/// numbered in a fixed order, so segments can be drawn in the same order
+	/// however they were found.
+	pub(super) fn of(&self, segment: EdgeIndex<Index>) -> (usize, usize) {
+		let bundle = self.bundle_of[&segment];
+		let position = self.bundles[bundle]
+			.segments
+			.iter()
+			.position(|&other| other == segment)
+			.expect("Segment not in its bundle");
+
+		(bundle, position)
+	}
+
+	pub(super) fn get(&self, bundle: usize) -> &Bundle {
+		&self.bundles[bundle]
+	}
+
+	/// The widest bundle a station is on, if it's on any.
+	pub(super) fn widest_at(
+		&self,
+		map: &Map,
+		station: StationIndex,
+	) -> Option<&Bundle> {
+		map.segments_at(station)
+			.map(|(segment, ..)| self.bundle_of[&segment])
+			.max_by_key(|&bundle| (self.bundles[bundle].segments.len(), bundle))
+			.map(|bundle| &self.bundles[bundle])
+	}
+}
+
+impl Bundle {
+	/// How far right of the middle of the bundle a segment is drawn, given
+	/// the endpoints it's drawn between.
+	pub(super) fn offset(&self, position: usize, start: StationIndex) -> f32 {
+		let middle = (self.segments.len() - 1) as f32 / 2.0;
+		let offset = (position as f32 - middle) * LINE_WIDTH;
+
+		if start == self.start {
+			offset
+		} else {
+			-offset
+		}
+	}
+
+	/// How far the outermost segments are from the middle of the bundle.
+	pub(super) fn half_width(&self) -> f32 {
+		(self.segments.len() - 1) as f32 / 2.0 * LINE_WIDTH
+	}
+
+	/// The direction the bundle leaves the given station in.
+	pub(super) fn direction_from(
+		&self,
+		map: &Map,
+		station: StationIndex,
+	) -> Vector {
+		if self.curve {
+			let segment = self.segments[0];
+			let (start, _) = map
+				.graph
+				.edge_endpoints(segment)
+				.expect("Edge index from bundle not in graph");
+			let (leaving, arriving) = map.curve_ends(segment);
+
+			return if station == start {
+				leaving
+			} else {
+				Vector::new(0.0, 0.0) - arriving
+			};
+		}
+
+		let position = map.graph[station].position;
+		let next = if station == self.start {
+			self.path[1]
+		} else {
+			self.path[self.path.len() - 2]
+		};
+
+		next - position
+	}
+}
+
+/// The points the path from `start` to `end` goes through, including the
+/// stations. Unless the path is a curve, points it doesn't turn at are left
+/// out.
+pub(super) fn path(
+	start: Point,
+	end: Point,
+	interpolation: &Interpolation,
+) -> Vec<Point> {
+	let corners = match interpolation {
+		Interpolation::Auto(d, Bend::Normal) => {
+			vec![interpolate_auto(start, end, *d)]
+		}
+		Interpolation::Auto(d, Bend::Flipped) => {
+			vec![interpolate_auto(end, start, *d)]
+		}
+		Interpolation::Waypoints(points) | Interpolation::Curve(points) => {
+			points.clone()
+		}
+	};
+
+	let mut path = vec![start];
+
+	for point in corners.into_iter().chain([end]) {
+		let last = path[path.len() - 1];
+		if magnitude(point - last) < 1e-3 {
+			continue;
+		}
+
+		// The last point is only a corner if the path turns there
+		if path.len() >= 2 && !interpolation.is_curve() {
+			let before = path[path.len() - 2];
+			let (a, b) = (last - before, point - last);
+			let cross = a.x * b.y - a.y * b.x;
+			let dot = a.x * b.x + a.y * b.y;
+			if cross.abs() < 1e-3 && dot > 0.0 {
+				path.pop();
+			}
+		}
+
+		path.push(point);
+	}
+
+	// Stations in the same place still have a path between them
+	if path.len() == 1 {
+		path.push(end);
+	}
+
+	path
+}
+
+impl Map {
+	/// Every segment grouped into bundles, including segments with nothing
+	/// running alongside them.
+	pub(super) fn bundles(&self) -> &Bundles {
+		self.bundles.get_or_init(|| self.group_bundles())
+	}
+
+	/// Forgets the bundles, after the segments or where they're drawn
+	/// changed, so they're grouped again when they're next needed.
+	pub(super) fn forget_bundles(&mut self) {
+		self.bundles.take();
+	}
+
+	fn group_bundles(&self) -> Bundles {
+		let graph = &self.graph;
+
+		let mut bundles: Vec<Bundle> = Vec::new();
+		let mut keys = HashMap::new();
+		let mut bundle_of = HashMap::with_capacity(graph.edge_count());
+
+		for index in self.segment_indices() {
+			let (start, end) = graph
+				.edge_endpoints(index)
+				.expect("Edge index from iteration not in graph");
+
+			let path = path(
+				graph[start].position,
+				graph[end].position,
+				&self.segment(index).interpolation,
+			);
+
+			// Paths are the same whichever way they're drawn, so the corners
+			// are always listed from the same end
+			let mut corners: Vec<_> = path[1..path.len() - 1]
+				.iter()
+				.map(|p| {
+					(
+						NotNan::new(p.x).expect("Corner is NaN"),
+						NotNan::new(p.y).expect("Corner is NaN"),
+					)
+				})
+				.collect();
+			if start > end {
+				corners.reverse();
+			}
+
+			let curve = self.segment(index).interpolation.is_curve();
+			let key = (start.min(end), start.max(end), corners, curve);
+
+			match keys.get(&key) {
+				Some(&i) => {
+					let bundle: &mut Bundle = &mut bundles[i];
+					bundle.segments.push(index);
+					bundle_of.insert(index, i);
+				}
+				None => {
+					keys.insert(key, bundles.len());
+					bundle_of.insert(index, bundles.len());
+					bundles.push(Bundle {
+						start,
+						end,
+						path,
+						curve,
+						segments: vec![index],
+					});
+				}
+			}
+		}
+
+		let lines = LineOrder {
+			map: self,
+			bundles: &bundles,
+			bundle_of: &bundle_of,
+		};
+		let orders: Vec<_> =
+			(0..bundles.len()).map(|i| lines.order(i)).collect();
+
+		for (bundle, segments) in bundles.iter_mut().zip(orders) {
+			bundle.segments = segments;
+		}
+
+		Bundles { bundles, bundle_of }
+	}
+}
+
+/// Orders the lines within bundles to keep them from crossing each other.
+///
+/// Two lines running through the same bundle are followed in both
+/// directions until they split up, and put on the same side of each other as
+/// the directions they split off in. If the two ends disagree the lines have
+/// to cross, and the same end is followed for every bundle they share, so
+/// they cross once, at the station where they split, instead of at every
+/// station along the way.
+struct LineOrder<'a> {
+	map: &'a Map,
+	bundles: &'a [Bundle],
+	bundle_of: &'a HashMap<EdgeIndex<Index>, usize>,
+}
+
+impl LineOrder<'_> {
+	/// The segments of a bundle, from left to right.
+	fn order(&self, bundle: usize) -> Vec<EdgeIndex<Index>> {
+		let mut ordered: Vec<EdgeIndex<Index>> = Vec::new();
+
+		// The comparison isn't necessarily transitive, which a sort could
+		// panic on, so the bundle is insertion sorted instead.
+		for &segment in &self.bundles[bundle].segments {
+			let position = ordered
+				.iter()
+				.position(|&other| {
+					self.compare(bundle, segment, other) == Ordering::Less
+				})
+				.unwrap_or(ordered.len());
+			ordered.insert(position, segment);
+		}
+
+		ordered
+	}
+
+	/// Whether segment `a` should be left of segment `b` in their bundle.
+	fn compare(
+		&self,
+		bundle: usize,
+		a: EdgeIndex<Index>,
+		b: EdgeIndex<Index>,
+	) -> Ordering {
+		let Bundle { start, end, .. } = self.bundles[bundle];
+
+		let forward = self.split(a, b, end);
+		// Right and left swap when looking back towards the start
+		let backward = self
+			.split(a, b, start)
+			.map(|(station, order)| (station, order.reverse()));
+
+		let map = self.map;
+		let by_line = || map.segment(a).line.cmp(&map.segment(b).line);
+
+		match (forward, backward) {
+			(Some((_, f)), Some((_, b))) if f == b => f,
+			// Whichever end is followed has to be the same for every bundle
+			// the lines share
+			(Some((f_station, f)), Some((b_station, b))) => {
+				if f_station < b_station {
+					f
+				} else {
+					b
+				}
+			}
+			(Some((_, order)), None) | (None, Some((_, order))) => order,
+			(None, None) => by_line(),
+		}
+	}
+
+	/// Follows two segments in the same bundle towards `station` until their
+	/// lines split up. Returns the station they split at, and whether `a`
+	/// leaves it to the right of `b`, looking towards it. Lines are followed
+	/// through complexes, where they can leave from another station.
+	///
+	/// Returns `None` if the lines never split, or if one of them ends or
+	/// branches before they do.
+	fn split(
+		&self,
+		mut a: EdgeIndex<Index>,
+		mut b: EdgeIndex<Index>,
+		mut station: StationIndex,
+	) -> Option<(StationIndex, Ordering)> {
+		let graph = &self.map.graph;
+
+		// Lines running around the same loop never split
+		for _ in 0..self.bundles.len() {
+			let (next_a, from_a) = self.map.continuing(a, station)?;
+			let (next_b, from_b) = self.map.continuing(b, station)?;
+
+			let bundle = self.bundle_of[&a];
+			let (bundle_a, bundle_b) =
+				(self.bundle_of[&next_a], self.bundle_of[&next_b]);
+
+			if bundle_a == bundle_b {
+				let (start, end) = graph
+					.edge_endpoints(next_a)
+					.expect("Edge index from bundle not in graph");
+				station = if start == from_a { end } else { start };
+				a = next_a;
+				b = next_b;
+				continue;
+			}
+
+			let incoming =
+				Vector::new(0.0, 0.0) - self.direction(bundle, station);
+			let turn = |(bundle, from)| {
+				let d = self.direction(bundle, from);
+				f32::atan2(
+					incoming.x * d.y - incoming.y * d.x,
+					incoming.x * d.x + incoming.y * d.y,
+				)
+			};
+
+			// Clockwise turns are positive, so the line turning further
+			// clockwise is on the right
+			return turn((bundle_a, from_a))
+				.partial_cmp(&turn((bundle_b, from_b)))
+				.filter(|&order| order != Ordering::Equal)
+				.map(|order| (station, order));
+		}
+
+		None
+	}
+
+	fn direction(&self, bundle: usize, station: StationIndex) -> Vector {
+		self.bundles[bundle].direction_from(self.map, station)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::map::{tests::TestMap, ExportFormat, ExportOptions, LineIndex};
+
+	/// The lines in the bundle from `start` to `end`, from left to right
+	/// looking towards `end`.
+	fn lines(map: &Map, start: usize, end: usize) -> Vec<LineIndex> {
+		let (start, end) = (StationIndex::new(start), StationIndex::new(end));
+		let bundle = map
+			.bundles()
+			.iter()
+			.find(|bundle| (bundle.start, bundle.end) == (start, end))
+			.expect("No bundle between the stations");
+
+		bundle
+			.segments
+			.iter()
+			.map(|&segment| map.segment(segment).line)
+			.collect()
+	}
+
+	#[test]
+	fn stable_without_crossings() {
+		let stations = [(0.0, 0.0), (100.0, 0.0), (200.0, 0.0)];
+		let parallel = TestMap::new(&stations)
+			.line(0, &[0, 1, 2])
+			.line(1, &[0, 1, 2])
+			.build();
+		assert_eq!(lines(&parallel, 0, 1), [0, 1]);
+		assert_eq!(lines(&parallel, 1, 2), [0, 1]);
+
+		// The order doesn't depend on which segment was added first
+		let reversed = TestMap::new(&stations)
+			.line(1, &[0, 1, 2])
+			.line(0, &[0, 1, 2])
+			.build();
+		assert_eq!(lines(&reversed, 0, 1), [0, 1]);
+		assert_eq!(lines(&reversed, 1, 2), [0, 1]);
+	}
+
+	#[test]
+	fn split() {
+		// Line 1 turns left at station 1, and line 0 turns right
+		let stations =
+			[(0.0, 0.0), (100.0, 0.0), (200.0, 100.0), (200.0, -100.0)];
+		let map = TestMap::new(&stations)
+			.line(0, &[0, 1, 2])
+			.line(1, &[0, 1, 3])
+			.build();
+
+		assert_eq!(lines(&map, 0, 1), [1, 0]);
+	}
+
+	#[test]
+	fn swap() {
+		// Line 0 joins from the left and leaves to the right, and line 1 the
+		// other way around, so they have to cross somewhere
+		let stations = [
+			(0.0, 0.0),
+			(100.0, 0.0),
+			(200.0, 0.0),
+			(-100.0, -100.0),
+			(-100.0, 100.0),
+			(300.0, 100.0),
+			(300.0, -100.0),
+		];
+		let map = TestMap::new(&stations)
+			.line(0, &[3, 0, 1, 2, 5])
+			.line(1, &[4, 0, 1, 2, 6])
+			.build();
+
+		// They cross once, at one end, rather than between the bundles
+		assert_eq!(lines(&map, 0, 1), lines(&map, 1, 2));
+	}
+
+	#[test]
+	fn regrouped_after_edits() {
+		let stations = [(0.0, 0.0), (100.0, 0.0)];
+		let mut map = TestMap::new(&stations)
+			.line(0, &[0, 1])
+			.line(1, &[0, 1])
+			.build();
+		assert_eq!(lines(&map, 0, 1), [0, 1]);
+
+		let segment = map
+			.segment_indices()
+			.find(|&index| map.segment(index).line == 0)
+			.expect("No segment on line 0");
+		let _ = map.remove_segment(map.segment(segment).id);
+		assert_eq!(lines(&map, 0, 1), [1]);
+	}
+
+	#[test]
+	fn cleared() {
+		let stations = [(0.0, 0.0), (100.0, 0.0)];
+		let mut map = TestMap::new(&stations).line(0, &[0, 1]).build();
+		assert_eq!(map.bundles().iter().count(), 1);
+
+		// Bundles of the segments cleared away aren't drawn
+		let undo = map.clear();
+		assert_eq!(map.bundles().iter().count(), 0);
+		assert!(map
+			.export(ExportFormat::Svg, &ExportOptions::default())
+			.is_ok());
+
+		let _ = undo.apply(&mut map);
+		assert_eq!(map.bundles().iter().count(), 1);
+	}
+}
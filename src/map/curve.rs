@@ -0,0 +1,94 @@
+// Copyright (C) 2022  ThatsNoMoon
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of version 3 of the GNU Affero General Public License as published
+// by the Free Software Foundation.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU Affero General Public License
+// for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use iced::{Point, Vector};
+use petgraph::graph::EdgeIndex;
+
+use super::{
+	bundle::path, geometry::magnitude, Index, Interpolation, Map, StationIndex,
+};
+
+impl Map {
+	/// Which way a curved segment is heading as it leaves its start and as
+	/// it arrives at its end. Where the segment's line carries on through a
+	/// station, the curve lines up with the segment on the other side, so
+	/// the line doesn't kink there.
+	pub(super) fn curve_ends(
+		&self,
+		segment: EdgeIndex<Index>,
+	) -> (Vector, Vector) {
+		let graph = &self.graph;
+		let (start, end) = graph
+			.edge_endpoints(segment)
+			.expect("Segment index not in graph");
+		let path = path(
+			graph[start].position,
+			graph[end].position,
+			&self.segment(segment).interpolation,
+		);
+
+		// Coming back along the segment is the opposite way to leaving
+		let back = self.through(segment, start, path[1]);
+		let arriving = self.through(segment, end, path[path.len() - 2]);
+
+		(Vector::new(0.0, 0.0) - back, arriving)
+	}
+
+	/// Which way a line is heading as it passes through `station`, coming
+	/// from `before` on `segment`.
+	fn through(
+		&self,
+		segment: EdgeIndex<Index>,
+		station: StationIndex,
+		before: Point,
+	) -> Vector {
+		let graph = &self.graph;
+		let position = graph[station].position;
+		let natural = position - before;
+
+		// Lines carrying on from elsewhere in a complex aren't lined up with
+		let next = match self.continuing(segment, station) {
+			Some((next, from)) if from == station => next,
+			_ => return natural,
+		};
+
+		let (start, end) = graph
+			.edge_endpoints(next)
+			.expect("Edge index from graph not in graph");
+		let path = path(
+			graph[start].position,
+			graph[end].position,
+			&self.segment(next).interpolation,
+		);
+		let after = if start == station {
+			path[1]
+		} else {
+			path[path.len() - 2]
+		};
+
+		match self.segment(next).interpolation {
+			// Both curves head the same way through the station
+			Interpolation::Curve(_) => (after - before) * 0.5,
+			// Curves run into straight segments head on
+			_ => {
+				let length = magnitude(after - position);
+				if length == 0.0 {
+					natural
+				} else {
+					(after - position) * (magnitude(natural) / length)
+				}
+			}
+		}
+	}
+}
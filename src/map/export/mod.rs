@@ -0,0 +1,130 @@
+// Copyright (C) 2022  ThatsNoMoon
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of version 3 of the GNU Affero General Public License as published
+// by the Free Software Foundation.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU Affero General Public License
+// for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+mod png;
+mod svg;
+
+use std::{fmt, io, sync::Arc};
+
+use iced::Point;
+
+use super::{
+	render::{self, Bounds, GRID_SIZE},
+	Map,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ExportFormat {
+	Svg,
+	Png,
+}
+
+impl ExportFormat {
+	pub(crate) fn extension(self) -> &'static str {
+		match self {
+			ExportFormat::Svg => "svg",
+			ExportFormat::Png => "png",
+		}
+	}
+
+	pub(crate) fn description(self) -> &'static str {
+		match self {
+			ExportFormat::Svg => "SVG image",
+			ExportFormat::Png => "PNG image",
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ExportOptions {
+	/// Whether to draw the editor's grid behind the map.
+	pub(crate) grid: bool,
+	pub(crate) background: Background,
+	/// Pixels per map unit for bitmap exports, so 1.0 is the map's own size.
+	pub(crate) scale: f32,
+}
+
+impl Default for ExportOptions {
+	fn default() -> Self {
+		Self {
+			grid: false,
+			background: Background::Theme,
+			scale: 2.0,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Background {
+	/// The same background color as the editor.
+	Theme,
+	Transparent,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) enum Error {
+	Io(Arc<io::Error>),
+	TooLarge(u32, u32),
+	Encoding(String),
+}
+
+impl fmt::Display for Error {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Error::Io(e) => write!(f, "{e}"),
+			Error::TooLarge(w, h) => {
+				write!(f, "a {w}x{h} image is too large to export")
+			}
+			Error::Encoding(e) => write!(f, "failed to encode image: {e}"),
+		}
+	}
+}
+
+impl From<io::Error> for Error {
+	fn from(e: io::Error) -> Self {
+		Self::Io(Arc::new(e))
+	}
+}
+
+impl Map {
+	pub(crate) fn export(
+		&self,
+		format: ExportFormat,
+		options: &ExportOptions,
+	) -> Result<Vec<u8>, Error> {
+		match format {
+			ExportFormat::Svg => Ok(svg::export(self, options).into_bytes()),
+			ExportFormat::Png => png::export(self, options),
+		}
+	}
+
+	/// The area an export of this map covers, leaving a grid square of margin
+	/// around everything drawn. The editor zooms to fit this too.
+	pub(super) fn export_bounds(&self) -> Bounds {
+		let mut bounds = Bounds::default();
+		render::draw_segments(self, None, &mut bounds);
+		render::draw_stations(self, None, &mut bounds);
+		render::draw_labels(self, None, &mut bounds);
+
+		if bounds.is_empty() {
+			Bounds {
+				min: Point::ORIGIN,
+				max: Point::ORIGIN,
+			}
+			.padded(GRID_SIZE)
+		} else {
+			bounds.padded(GRID_SIZE)
+		}
+	}
+}
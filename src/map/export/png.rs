@@ -0,0 +1,184 @@
+// Copyright (C) 2022  ThatsNoMoon
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of version 3 of the GNU Affero General Public License as published
+// by the Free Software Foundation.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU Affero General Public License
+// for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use iced::{Color, Point};
+use tiny_skia::{
+	FillRule, LineCap, LineJoin, Paint, PathBuilder, Pixmap, Stroke, Transform,
+};
+
+use super::{Background, Error, ExportOptions};
+use crate::{
+	color::ColorExt,
+	map::{
+		geometry::{Arc, PathCommand, Shape},
+		render::{self, Cap, Pen, Surface, BACKGROUND_COLOR},
+		Map,
+	},
+};
+
+/// Rasterizes the map in software, so no GPU is needed.
+pub(super) fn export(
+	map: &Map,
+	options: &ExportOptions,
+) -> Result<Vec<u8>, Error> {
+	let bounds = map.export_bounds();
+
+	let width = (bounds.width() * options.scale).ceil() as u32;
+	let height = (bounds.height() * options.scale).ceil() as u32;
+
+	let mut pixmap =
+		Pixmap::new(width, height).ok_or(Error::TooLarge(width, height))?;
+
+	match options.background {
+		Background::Theme => {
+			pixmap.fill(to_skia(Color::from_rgb32(BACKGROUND_COLOR)))
+		}
+		Background::Transparent => (),
+	}
+
+	let mut surface = PngSurface {
+		pixmap,
+		transform: Transform::from_translate(-bounds.min.x, -bounds.min.y)
+			.post_scale(options.scale, options.scale),
+	};
+
+	if options.grid {
+		render::draw_grid(bounds.min, bounds.max, &mut surface);
+	}
+
+	render::draw_segments(map, None, &mut surface);
+	render::draw_stations(map, None, &mut surface);
+	render::draw_labels(map, None, &mut surface);
+
+	surface
+		.pixmap
+		.encode_png()
+		.map_err(|e| Error::Encoding(e.to_string()))
+}
+
+struct PngSurface {
+	pixmap: Pixmap,
+	transform: Transform,
+}
+
+impl Surface for PngSurface {
+	fn fill(&mut self, shape: &Shape, color: Color) {
+		if let Some(path) = to_path(shape) {
+			self.pixmap.fill_path(
+				&path,
+				&paint(color),
+				FillRule::Winding,
+				self.transform,
+				None,
+			);
+		}
+	}
+
+	fn stroke(&mut self, shape: &Shape, pen: Pen) {
+		let (line_cap, line_join) = match pen.cap {
+			Cap::Butt => (LineCap::Butt, LineJoin::Miter),
+			Cap::Round => (LineCap::Round, LineJoin::Round),
+		};
+
+		if let Some(path) = to_path(shape) {
+			self.pixmap.stroke_path(
+				&path,
+				&paint(pen.color),
+				&Stroke {
+					width: pen.width,
+					line_cap,
+					line_join,
+					..Stroke::default()
+				},
+				self.transform,
+				None,
+			);
+		}
+	}
+}
+
+fn paint(color: Color) -> Paint<'static> {
+	let mut paint = Paint::default();
+	paint.set_color(to_skia(color));
+	paint.anti_alias = true;
+	paint
+}
+
+fn to_skia(color: Color) -> tiny_skia::Color {
+	tiny_skia::Color::from_rgba(color.r, color.g, color.b, color.a)
+		.unwrap_or(tiny_skia::Color::BLACK)
+}
+
+fn to_path(shape: &Shape) -> Option<tiny_skia::Path> {
+	let mut b = PathBuilder::new();
+	let mut current = Point::ORIGIN;
+
+	for command in shape.commands() {
+		match *command {
+			PathCommand::MoveTo(p) => {
+				b.move_to(p.x, p.y);
+				current = p;
+			}
+			PathCommand::LineTo(p) => {
+				b.line_to(p.x, p.y);
+				current = p;
+			}
+			PathCommand::ArcTo {
+				to,
+				radius,
+				clockwise,
+			} => {
+				arc_to(&mut b, current, to, radius, clockwise);
+				current = to;
+			}
+			PathCommand::QuadTo { control, to } => {
+				b.quad_to(control.x, control.y, to.x, to.y);
+				current = to;
+			}
+			PathCommand::CubicTo {
+				control_a: a,
+				control_b: c,
+				to,
+			} => {
+				b.cubic_to(a.x, a.y, c.x, c.y, to.x, to.y);
+				current = to;
+			}
+			PathCommand::Circle { center, radius } => {
+				b.push_circle(center.x, center.y, radius)
+			}
+			PathCommand::Close => b.close(),
+		}
+	}
+
+	b.finish()
+}
+
+/// Draws the arc described by [`PathCommand::ArcTo`] as cubic Béziers, since
+/// tiny-skia has no arcs of its own.
+fn arc_to(
+	b: &mut PathBuilder,
+	from: Point,
+	to: Point,
+	radius: f32,
+	clockwise: bool,
+) {
+	match Arc::between(from, to, radius, clockwise) {
+		Some(arc) => {
+			for [a, c, to] in arc.cubics() {
+				b.cubic_to(a.x, a.y, c.x, c.y, to.x, to.y);
+			}
+		}
+		None => b.line_to(to.x, to.y),
+	}
+}
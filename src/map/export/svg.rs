@@ -0,0 +1,219 @@
+// Copyright (C) 2022  ThatsNoMoon
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of version 3 of the GNU Affero General Public License as published
+// by the Free Software Foundation.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU Affero General Public License
+// for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::fmt::Write;
+
+use iced::{Color, Point};
+
+use super::{Background, ExportOptions};
+use crate::{
+	color::ColorExt,
+	map::{
+		font,
+		geometry::{PathCommand, Shape},
+		render::{self, Cap, Pen, Surface, BACKGROUND_COLOR},
+		Map,
+	},
+};
+
+pub(super) fn export(map: &Map, options: &ExportOptions) -> String {
+	let bounds = map.export_bounds();
+
+	let mut svg = SvgSurface { out: String::new() };
+
+	let _ = writeln!(
+		svg.out,
+		r#"<?xml version="1.0" encoding="UTF-8"?>
+<svg xmlns="http://www.w3.org/2000/svg" version="1.1" width="{w}" height="{h}" viewBox="{x} {y} {w} {h}">"#,
+		x = num(bounds.min.x),
+		y = num(bounds.min.y),
+		w = num(bounds.width()),
+		h = num(bounds.height()),
+	);
+
+	if options.background == Background::Theme {
+		let background = Shape::new(|s| {
+			s.move_to(bounds.min);
+			s.line_to(Point::new(bounds.max.x, bounds.min.y));
+			s.line_to(bounds.max);
+			s.line_to(Point::new(bounds.min.x, bounds.max.y));
+			s.close();
+		});
+		svg.fill(&background, Color::from_rgb32(BACKGROUND_COLOR));
+	}
+
+	if options.grid {
+		render::draw_grid(bounds.min, bounds.max, &mut svg);
+	}
+
+	render::draw_segments(map, None, &mut svg);
+	render::draw_stations(map, None, &mut svg);
+	render::draw_labels(map, None, &mut svg);
+
+	svg.out.push_str("</svg>\n");
+
+	svg.out
+}
+
+struct SvgSurface {
+	out: String,
+}
+
+impl Surface for SvgSurface {
+	fn fill(&mut self, shape: &Shape, color: Color) {
+		let _ = writeln!(
+			self.out,
+			r#"<path d="{}" fill="{}"{}/>"#,
+			path_data(shape),
+			hex(color),
+			opacity("fill-opacity", color),
+		);
+	}
+
+	fn stroke(&mut self, shape: &Shape, pen: Pen) {
+		let (cap, join) = match pen.cap {
+			Cap::Butt => ("butt", "miter"),
+			Cap::Round => ("round", "round"),
+		};
+
+		let _ = writeln!(
+			self.out,
+			r#"<path d="{}" fill="none" stroke="{}"{} stroke-width="{}" stroke-linecap="{cap}" stroke-linejoin="{join}"/>"#,
+			path_data(shape),
+			hex(pen.color),
+			opacity("stroke-opacity", pen.color),
+			num(pen.width),
+		);
+	}
+
+	fn text(
+		&mut self,
+		content: &str,
+		top_left: Point,
+		size: f32,
+		color: Color,
+	) {
+		let _ = writeln!(
+			self.out,
+			r#"<text x="{}" y="{}" font-family="{}, sans-serif" font-size="{}" fill="{}"{}>{}</text>"#,
+			num(top_left.x),
+			num(top_left.y + font::ascent(size)),
+			font::NAME,
+			num(size * font::em_ratio()),
+			hex(color),
+			opacity("fill-opacity", color),
+			escape(content),
+		);
+	}
+}
+
+fn path_data(shape: &Shape) -> String {
+	let mut d = String::new();
+
+	for command in shape.commands() {
+		if !d.is_empty() {
+			d.push(' ');
+		}
+
+		let _ = match *command {
+			PathCommand::MoveTo(p) => write!(d, "M{} {}", num(p.x), num(p.y)),
+			PathCommand::LineTo(p) => write!(d, "L{} {}", num(p.x), num(p.y)),
+			PathCommand::ArcTo {
+				to,
+				radius,
+				clockwise,
+			} => write!(
+				d,
+				"A{r} {r} 0 0 {} {} {}",
+				u8::from(clockwise),
+				num(to.x),
+				num(to.y),
+				r = num(radius),
+			),
+			PathCommand::QuadTo { control, to } => write!(
+				d,
+				"Q{} {} {} {}",
+				num(control.x),
+				num(control.y),
+				num(to.x),
+				num(to.y),
+			),
+			PathCommand::CubicTo {
+				control_a: a,
+				control_b: b,
+				to,
+			} => write!(
+				d,
+				"C{} {} {} {} {} {}",
+				num(a.x),
+				num(a.y),
+				num(b.x),
+				num(b.y),
+				num(to.x),
+				num(to.y),
+			),
+			PathCommand::Circle { center, radius } => write!(
+				d,
+				"M{right} {y} A{r} {r} 0 1 0 {left} {y} A{r} {r} 0 1 0 {right} \
+				 {y} Z",
+				right = num(center.x + radius),
+				left = num(center.x - radius),
+				y = num(center.y),
+				r = num(radius),
+			),
+			PathCommand::Close => write!(d, "Z"),
+		};
+	}
+
+	d
+}
+
+fn hex(color: Color) -> String {
+	format!("#{:06x}", color.to_rgb32())
+}
+
+fn opacity(attribute: &str, color: Color) -> String {
+	if color.a < 1.0 {
+		format!(r#" {attribute}="{}""#, num(color.a))
+	} else {
+		String::new()
+	}
+}
+
+fn escape(text: &str) -> String {
+	let mut escaped = String::with_capacity(text.len());
+
+	for c in text.chars() {
+		match c {
+			'&' => escaped.push_str("&amp;"),
+			'<' => escaped.push_str("&lt;"),
+			'>' => escaped.push_str("&gt;"),
+			_ => escaped.push(c),
+		}
+	}
+
+	escaped
+}
+
+/// Formats a coordinate without unnecessary precision.
+fn num(x: f32) -> String {
+	let rounded = (x * 1000.0).round() / 1000.0;
+
+	// Avoid writing out negative zero
+	if rounded == 0.0 {
+		"0".to_owned()
+	} else {
+		rounded.to_string()
+	}
+}
@@ -0,0 +1,142 @@
+// Copyright (C) 2022  ThatsNoMoon
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of version 3 of the GNU Affero General Public License as published
+// by the Free Software Foundation.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU Affero General Public License
+// for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! The font labels are drawn in. It's bundled rather than loaded from the
+//! system so that labels are measured, drawn, and exported the same way
+//! everywhere.
+
+use std::sync::OnceLock;
+
+use ab_glyph::{Font, FontRef, GlyphId, OutlineCurve};
+use iced::{Point, Size};
+
+use super::geometry::Shape;
+
+pub(super) const NAME: &str = "Lato";
+pub(super) const BYTES: &[u8] =
+	include_bytes!("../../assets/fonts/Lato-Regular.ttf");
+
+fn font() -> &'static FontRef<'static> {
+	static FONT: OnceLock<FontRef<'static>> = OnceLock::new();
+
+	FONT.get_or_init(|| {
+		FontRef::try_from_slice(BYTES).expect("Bundled font is invalid")
+	})
+}
+
+/// How much larger than its height the font's em square is. Text sizes here
+/// are heights, as in iced, but SVG font sizes are em sizes.
+pub(super) fn em_ratio() -> f32 {
+	let font = font();
+	font.units_per_em().unwrap_or(1000.0) / font.height_unscaled()
+}
+
+/// Distance from the top of a line of text to its baseline.
+pub(super) fn ascent(size: f32) -> f32 {
+	let font = font();
+	font.ascent_unscaled() * size / font.height_unscaled()
+}
+
+/// Glyphs of the given text along with their horizontal offset from the
+/// start of the line, in font units.
+fn layout(text: &str) -> impl Iterator<Item = (GlyphId, f32)> + '_ {
+	let font = font();
+	let mut x = 0.0;
+	let mut previous = None;
+
+	text.chars().map(move |c| {
+		let glyph = font.glyph_id(c);
+		if let Some(previous) = previous {
+			x += font.kern_unscaled(previous, glyph);
+		}
+		let position = x;
+		x += font.h_advance_unscaled(glyph);
+		previous = Some(glyph);
+		(glyph, position)
+	})
+}
+
+pub(super) fn measure(text: &str, size: f32) -> Size {
+	let font = font();
+	let scale = size / font.height_unscaled();
+
+	let width = layout(text)
+		.last()
+		.map(|(glyph, x)| x + font.h_advance_unscaled(glyph))
+		.unwrap_or(0.0);
+
+	Size::new(width * scale, size)
+}
+
+/// The outlines of the given text, for surfaces that can't draw text
+/// themselves.
+pub(super) fn outline(text: &str, top_left: Point, size: f32) -> Shape {
+	let font = font();
+	let scale = size / font.height_unscaled();
+	let baseline = top_left.y + ascent(size);
+
+	Shape::new(|s| {
+		for (glyph, x) in layout(text) {
+			let outline = match font.outline(glyph) {
+				Some(outline) => outline,
+				None => continue,
+			};
+
+			let origin_x = top_left.x + x * scale;
+			let point = |p: ab_glyph::Point| {
+				Point::new(origin_x + p.x * scale, baseline - p.y * scale)
+			};
+
+			let mut current = None;
+			for curve in outline.curves {
+				let start = match curve {
+					OutlineCurve::Line(start, ..)
+					| OutlineCurve::Quad(start, ..)
+					| OutlineCurve::Cubic(start, ..) => point(start),
+				};
+
+				if current != Some(start) {
+					if current.is_some() {
+						s.close();
+					}
+					s.move_to(start);
+				}
+
+				let end = match curve {
+					OutlineCurve::Line(_, end) => {
+						let end = point(end);
+						s.line_to(end);
+						end
+					}
+					OutlineCurve::Quad(_, control, end) => {
+						let end = point(end);
+						s.quadratic_curve_to(point(control), end);
+						end
+					}
+					OutlineCurve::Cubic(_, a, b, end) => {
+						let end = point(end);
+						s.bezier_curve_to(point(a), point(b), end);
+						end
+					}
+				};
+
+				current = Some(end);
+			}
+
+			if current.is_some() {
+				s.close();
+			}
+		}
+	})
+}
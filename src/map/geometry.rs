@@ -0,0 +1,569 @@
+// Copyright (C) 2022  ThatsNoMoon
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of version 3 of the GNU Affero General Public License as published
+// by the Free Software Foundation.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU Affero General Public License
+// for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::f32::consts::{FRAC_PI_2, PI};
+
+use iced::{Point, Vector};
+
+use super::InterpolationDirection;
+
+/// A backend-independent path, recorded so the same geometry can be drawn on
+/// the canvas and written out by the exporters.
+#[derive(Debug, Clone, Default)]
+pub(super) struct Shape {
+	commands: Vec<PathCommand>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) enum PathCommand {
+	MoveTo(Point),
+	LineTo(Point),
+	/// An SVG-style arc from the current point to `to`, taking the shorter
+	/// way around in the given direction.
+	ArcTo {
+		to: Point,
+		radius: f32,
+		clockwise: bool,
+	},
+	QuadTo {
+		control: Point,
+		to: Point,
+	},
+	CubicTo {
+		control_a: Point,
+		control_b: Point,
+		to: Point,
+	},
+	Circle {
+		center: Point,
+		radius: f32,
+	},
+	Close,
+}
+
+impl Shape {
+	pub(super) fn new(f: impl FnOnce(&mut Self)) -> Self {
+		let mut shape = Self::default();
+		f(&mut shape);
+		shape
+	}
+
+	pub(super) fn circle(center: Point, radius: f32) -> Self {
+		Self::new(|s| s.push_circle(center, radius))
+	}
+
+	pub(super) fn line(from: Point, to: Point) -> Self {
+		Self::new(|s| {
+			s.move_to(from);
+			s.line_to(to);
+		})
+	}
+
+	/// A line from `a` to `b` with rounded ends, `radius` thick on either side.
+	pub(super) fn capsule(a: Point, b: Point, radius: f32) -> Self {
+		let n = normal(b - a) * radius;
+		if n == Vector::new(0.0, 0.0) {
+			return Self::circle(a, radius);
+		}
+
+		Self::new(|s| {
+			s.move_to(a + n);
+			s.line_to(b + n);
+			s.arc_to(b - n, radius, false);
+			s.line_to(a - n);
+			s.arc_to(a + n, radius, false);
+			s.close();
+		})
+	}
+
+	pub(super) fn commands(&self) -> &[PathCommand] {
+		&self.commands
+	}
+
+	pub(super) fn move_to(&mut self, point: Point) {
+		self.commands.push(PathCommand::MoveTo(point));
+	}
+
+	pub(super) fn line_to(&mut self, point: Point) {
+		self.commands.push(PathCommand::LineTo(point));
+	}
+
+	pub(super) fn arc_to(&mut self, to: Point, radius: f32, clockwise: bool) {
+		self.commands.push(PathCommand::ArcTo {
+			to,
+			radius,
+			clockwise,
+		});
+	}
+
+	pub(super) fn quadratic_curve_to(&mut self, control: Point, to: Point) {
+		self.commands.push(PathCommand::QuadTo { control, to });
+	}
+
+	pub(super) fn bezier_curve_to(
+		&mut self,
+		control_a: Point,
+		control_b: Point,
+		to: Point,
+	) {
+		self.commands.push(PathCommand::CubicTo {
+			control_a,
+			control_b,
+			to,
+		});
+	}
+
+	pub(super) fn push_circle(&mut self, center: Point, radius: f32) {
+		self.commands.push(PathCommand::Circle { center, radius });
+	}
+
+	pub(super) fn close(&mut self) {
+		self.commands.push(PathCommand::Close);
+	}
+
+	/// Every point this shape passes through, along with how far the shape
+	/// may extend around it.
+	pub(super) fn extents(&self) -> Vec<(Point, f32)> {
+		let mut extents = Vec::with_capacity(self.commands.len());
+		let mut current = Point::ORIGIN;
+
+		for command in &self.commands {
+			match *command {
+				PathCommand::MoveTo(p) | PathCommand::LineTo(p) => {
+					extents.push((p, 0.0));
+					current = p;
+				}
+				PathCommand::ArcTo {
+					to,
+					radius,
+					clockwise,
+				} => {
+					match Arc::between(current, to, radius, clockwise) {
+						Some(arc) => extents.push((arc.center, arc.radius)),
+						None => extents.push((to, 0.0)),
+					}
+					current = to;
+				}
+				// Curves stay within the hull of their control points
+				PathCommand::QuadTo { control, to } => {
+					extents.extend([(control, 0.0), (to, 0.0)]);
+					current = to;
+				}
+				PathCommand::CubicTo {
+					control_a,
+					control_b,
+					to,
+				} => {
+					extents.extend([
+						(control_a, 0.0),
+						(control_b, 0.0),
+						(to, 0.0),
+					]);
+					current = to;
+				}
+				PathCommand::Circle { center, radius } => {
+					extents.push((center, radius))
+				}
+				PathCommand::Close => (),
+			}
+		}
+
+		extents
+	}
+
+	/// Approximates this shape with straight lines, as a list of polylines.
+	pub(super) fn flatten(&self) -> Vec<Vec<Point>> {
+		/// How many lines each curve is split into.
+		const STEPS: usize = 8;
+
+		let mut polylines = Vec::new();
+		let mut points = Vec::new();
+		let mut current = Point::ORIGIN;
+
+		for command in &self.commands {
+			if points.is_empty() {
+				points.push(current);
+			}
+
+			match *command {
+				PathCommand::MoveTo(p) => {
+					polylines.push(std::mem::take(&mut points));
+					points.push(p);
+					current = p;
+				}
+				PathCommand::LineTo(p) => {
+					points.push(p);
+					current = p;
+				}
+				PathCommand::ArcTo {
+					to,
+					radius,
+					clockwise,
+				} => {
+					match Arc::between(current, to, radius, clockwise) {
+						Some(arc) => points.extend(
+							(1..=STEPS)
+								.map(|i| arc.point(i as f32 / STEPS as f32)),
+						),
+						None => points.push(to),
+					}
+					current = to;
+				}
+				PathCommand::QuadTo { control, to } => {
+					let from = current;
+					points.extend((1..=STEPS).map(|i| {
+						let t = i as f32 / STEPS as f32;
+						let u = 1.0 - t;
+						lerp3(from, control, to, u * u, 2.0 * u * t, t * t)
+					}));
+					current = to;
+				}
+				PathCommand::CubicTo {
+					control_a,
+					control_b,
+					to,
+				} => {
+					let from = current;
+					points.extend((1..=STEPS).map(|i| {
+						let t = i as f32 / STEPS as f32;
+						let u = 1.0 - t;
+						let a = lerp3(
+							from,
+							control_a,
+							control_b,
+							u * u * u,
+							3.0 * u * u * t,
+							3.0 * u * t * t,
+						);
+						Point::new(
+							a.x + t * t * t * to.x,
+							a.y + t * t * t * to.y,
+						)
+					}));
+					current = to;
+				}
+				PathCommand::Circle { center, radius } => {
+					let circle = (0..=STEPS * 4)
+						.map(|i| {
+							let angle =
+								i as f32 / (STEPS * 4) as f32 * 2.0 * PI;
+							Point::new(
+								center.x + radius * angle.cos(),
+								center.y + radius * angle.sin(),
+							)
+						})
+						.collect();
+					polylines.push(circle);
+				}
+				PathCommand::Close => {
+					if let Some(&first) = points.first() {
+						points.push(first);
+						current = first;
+					}
+				}
+			}
+		}
+
+		polylines.push(points);
+		polylines.retain(|points| points.len() > 1);
+		polylines
+	}
+}
+
+/// Weighted sum of three points, with weights that don't necessarily sum to
+/// one.
+fn lerp3(a: Point, b: Point, c: Point, wa: f32, wb: f32, wc: f32) -> Point {
+	Point::new(
+		a.x * wa + b.x * wb + c.x * wc,
+		a.y * wa + b.y * wb + c.y * wc,
+	)
+}
+
+/// The circular arc described by [`PathCommand::ArcTo`].
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Arc {
+	pub(super) center: Point,
+	pub(super) radius: f32,
+	pub(super) start_angle: f32,
+	/// Angle swept from the start, which is positive for clockwise arcs.
+	pub(super) sweep: f32,
+}
+
+impl Arc {
+	/// Finds the arc from `from` to `to`, or `None` if it's degenerate and
+	/// should be drawn as a straight line.
+	pub(super) fn between(
+		from: Point,
+		to: Point,
+		radius: f32,
+		clockwise: bool,
+	) -> Option<Self> {
+		// Half of the chord, relative to its midpoint
+		let half = (from - to) * 0.5;
+		let half_length = magnitude(half);
+
+		if radius <= 0.0 || half_length == 0.0 {
+			return None;
+		}
+
+		let radius = radius.max(half_length);
+
+		// The shorter way around keeps the center on the same side of the
+		// chord as the direction being turned in.
+		let mut offset = ((radius.powi(2) - half_length.powi(2)).max(0.0))
+			.sqrt() / half_length;
+		if clockwise {
+			offset = -offset;
+		}
+		let mid = Point::new((from.x + to.x) / 2.0, (from.y + to.y) / 2.0);
+		let center =
+			Point::new(mid.x - offset * half.y, mid.y + offset * half.x);
+
+		let start_angle = f32::atan2(from.y - center.y, from.x - center.x);
+		let end_angle = f32::atan2(to.y - center.y, to.x - center.x);
+
+		let mut sweep = end_angle - start_angle;
+		if clockwise && sweep < 0.0 {
+			sweep += 2.0 * PI;
+		} else if !clockwise && sweep > 0.0 {
+			sweep -= 2.0 * PI;
+		}
+
+		Some(Self {
+			center,
+			radius,
+			start_angle,
+			sweep,
+		})
+	}
+
+	/// The point `t` of the way along the arc.
+	pub(super) fn point(&self, t: f32) -> Point {
+		let angle = self.start_angle + self.sweep * t;
+		Point::new(
+			self.center.x + self.radius * angle.cos(),
+			self.center.y + self.radius * angle.sin(),
+		)
+	}
+
+	/// Approximates the arc with cubic Béziers, for backends without arcs of
+	/// their own. Returns the control points and end of each curve.
+	pub(super) fn cubics(&self) -> impl Iterator<Item = [Point; 3]> + '_ {
+		let pieces = (self.sweep.abs() / FRAC_PI_2).ceil().max(1.0) as usize;
+		let step = self.sweep / pieces as f32;
+		let k = 4.0 / 3.0 * (step / 4.0).tan() * self.radius;
+
+		(0..pieces).map(move |i| {
+			let a0 = self.start_angle + step * i as f32;
+			let a1 = a0 + step;
+
+			let p0 = self.point(i as f32 / pieces as f32);
+			let p1 = self.point((i + 1) as f32 / pieces as f32);
+
+			[
+				Point::new(p0.x - k * a0.sin(), p0.y + k * a0.cos()),
+				Point::new(p1.x + k * a1.sin(), p1.y - k * a1.cos()),
+				p1,
+			]
+		})
+	}
+}
+
+pub(super) fn magnitude(v: Vector) -> f32 {
+	(v.x.powi(2) + v.y.powi(2)).sqrt()
+}
+
+/// The distance from `p` to the closest point on the line from `a` to `b`.
+pub(super) fn distance_to_line(p: Point, a: Point, b: Point) -> f32 {
+	magnitude(p - closest_on_line(p, a, b))
+}
+
+/// The point on the line from `a` to `b` closest to `p`.
+pub(super) fn closest_on_line(p: Point, a: Point, b: Point) -> Point {
+	let d = b - a;
+	let length = d.x * d.x + d.y * d.y;
+	if length == 0.0 {
+		return a;
+	}
+
+	let t = (((p.x - a.x) * d.x + (p.y - a.y) * d.y) / length).clamp(0.0, 1.0);
+
+	a + d * t
+}
+
+/// A unit vector a quarter turn clockwise from `v`, or zero if `v` is.
+pub(super) fn normal(v: Vector) -> Vector {
+	let length = magnitude(v);
+	if length == 0.0 {
+		Vector::new(0.0, 0.0)
+	} else {
+		Vector::new(-v.y / length, v.x / length)
+	}
+}
+
+pub(super) fn interpolate_auto(
+	start: Point,
+	end: Point,
+	direction: InterpolationDirection,
+) -> Point {
+	use InterpolationDirection::*;
+
+	let dx = end.x - start.x;
+	let dy = end.y - start.y;
+
+	let horizontal = || Point::new(end.x - dy.abs() * dx.signum(), start.y);
+	let vertical = || Point::new(start.x, end.y - dx.abs() * dy.signum());
+	let diagonal = || {
+		if dx.abs() > dy.abs() {
+			Point::new(start.x + dy.abs() * dx.signum(), end.y)
+		} else {
+			Point::new(end.x, start.y + dx.abs() * dy.signum())
+		}
+	};
+
+	match direction {
+		Horizontal => horizontal(),
+		Vertical => vertical(),
+		Diagonal => diagonal(),
+		Auto => {
+			if dx.abs() > dy.abs() {
+				horizontal()
+			} else {
+				vertical()
+			}
+		}
+	}
+}
+
+/// Draws straight lines through each of the points in turn, rounding the
+/// corners between them with the given radius. The whole path is moved
+/// `offset` to the right, with each corner rounded around the same center,
+/// so paths drawn with different offsets stay parallel.
+pub(super) fn rounded_path(
+	points: &[Point],
+	radius: f32,
+	offset: f32,
+	b: &mut Shape,
+) {
+	let (first, last) = match points {
+		[first, .., last] => (*first, *last),
+		_ => return,
+	};
+
+	let start = normal(points[1] - first);
+	b.move_to(first + start * offset);
+
+	for (i, corner) in points.windows(3).enumerate() {
+		let [start, mid, end] = [corner[0], corner[1], corner[2]];
+
+		let (first, second) = (mid - start, end - mid);
+		let (first_length, second_length) =
+			(magnitude(first), magnitude(second));
+
+		let (n1, n2) = (normal(first), normal(second));
+
+		let cross = first.x * second.y - first.y * second.x;
+		let dot = first.x * second.x + first.y * second.y;
+
+		// Without a corner, the path just carries on straight
+		if first_length < 1e-3 || second_length < 1e-3 || cross.abs() < 1e-3 {
+			if dot < 0.0 {
+				b.line_to(mid + n1 * offset);
+				b.line_to(mid + n2 * offset);
+			}
+			continue;
+		}
+
+		let u1 = first * (1.0 / first_length);
+		let u2 = second * (1.0 / second_length);
+
+		// Legs between two corners are shared by both of them, so each can
+		// only have half
+		let first_room = if i == 0 {
+			first_length
+		} else {
+			first_length / 2.0
+		};
+		let second_room = if i + 3 == points.len() {
+			second_length
+		} else {
+			second_length / 2.0
+		};
+
+		// Distance from the corner to where the rounding starts and ends,
+		// which can't be further than there's room for on either leg
+		let half_tan = (cross.atan2(dot).abs() / 2.0).tan();
+		let radius = radius
+			.min(first_room / half_tan)
+			.min(second_room / half_tan);
+		let tangent = radius * half_tan;
+
+		// Which side of the path the center of the corner is on
+		let side = cross.signum();
+		let inner_radius = radius - side * offset;
+
+		if inner_radius > 0.0 {
+			b.line_to(mid - u1 * tangent + n1 * offset);
+			b.arc_to(
+				mid + u2 * tangent + n2 * offset,
+				inner_radius,
+				side > 0.0,
+			);
+		} else {
+			// Too far inside the corner to be rounded, so the offset legs
+			// just meet
+			let n_dot = n1.x * n2.x + n1.y * n2.y;
+			b.line_to(mid + (n1 + n2) * (offset / (1.0 + n_dot)));
+		}
+	}
+
+	let end = normal(last - points[points.len() - 2]);
+	b.line_to(last + end * offset);
+}
+
+/// Draws a smooth curve through each of the points in turn, leaving the first
+/// point and arriving at the last heading the ways given by `ends`. The
+/// whole curve is moved `offset` to the right, like [`rounded_path`].
+pub(super) fn curved_path(
+	points: &[Point],
+	ends: (Vector, Vector),
+	offset: f32,
+	b: &mut Shape,
+) {
+	if points.len() < 2 {
+		return;
+	}
+
+	// Each point between the ends is passed heading from the point before it
+	// towards the point after it
+	let last = points.len() - 1;
+	let heading = |i: usize| match i {
+		0 => ends.0,
+		i if i == last => ends.1,
+		i => (points[i + 1] - points[i - 1]) * 0.5,
+	};
+
+	b.move_to(points[0] + normal(heading(0)) * offset);
+
+	for i in 0..last {
+		let (leaving, arriving) = (heading(i), heading(i + 1));
+		let (from, to) = (normal(leaving) * offset, normal(arriving) * offset);
+
+		b.bezier_curve_to(
+			points[i] + leaving * (1.0 / 3.0) + from,
+			points[i + 1] - arriving * (1.0 / 3.0) + to,
+			points[i + 1] + to,
+		);
+	}
+}
@@ -0,0 +1,248 @@
+// Copyright (C) 2022  ThatsNoMoon
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of version 3 of the GNU Affero General Public License as published
+// by the Free Software Foundation.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU Affero General Public License
+// for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use super::{
+	Line, LineIndex, Map, Redraw, Segment, SegmentId, Service, Station,
+	StationId, Style,
+};
+
+/// A change to a map. Applying an edit returns the edit that reverses it, so
+/// the history only needs to store those.
+#[derive(Debug, Clone)]
+#[must_use = "edits should be recorded so they can be undone"]
+pub(crate) enum Edit {
+	InsertStation(Station),
+	/// Removes a station along with its segments and transfers, without
+	/// joining them.
+	DeleteStation(StationId),
+	/// Swaps in a new version of the station with the same ID.
+	ReplaceStation(Station),
+	InsertSegment {
+		start: StationId,
+		end: StationId,
+		segment: Segment,
+	},
+	DeleteSegment(SegmentId),
+	/// Swaps in a new version of the segment with the same ID, between the
+	/// same stations.
+	ReplaceSegment(Segment),
+	/// Inserts a line, moving later lines along to make room for it.
+	InsertLine(LineIndex, Line),
+	/// Removes a line that has no segments left.
+	DeleteLine(LineIndex),
+	ReplaceLine(LineIndex, Line),
+	MoveLine {
+		from: LineIndex,
+		to: LineIndex,
+	},
+	ReplaceServices(LineIndex, Vec<Service>),
+	ReplaceStyle(Style),
+	InsertTransfer(StationId, StationId),
+	DeleteTransfer(StationId, StationId),
+	/// Replaces the whole map, for changes too broad to track piece by piece.
+	Restore(Box<Map>),
+	/// Several edits applied in order, treated as one step.
+	Group(Vec<Edit>),
+}
+
+impl Edit {
+	pub(crate) fn apply(self, map: &mut Map) -> Edit {
+		match self {
+			Edit::InsertStation(station) => map.insert_station(station),
+			Edit::DeleteStation(id) => map.delete_station(id),
+			Edit::ReplaceStation(station) => map.replace_station(station),
+			Edit::InsertSegment {
+				start,
+				end,
+				segment,
+			} => map.insert_segment(start, end, segment),
+			Edit::DeleteSegment(id) => map.delete_segment(id),
+			Edit::ReplaceSegment(segment) => map.replace_segment(segment),
+			Edit::InsertLine(index, line) => map.insert_line(index, line),
+			Edit::DeleteLine(index) => map.delete_line(index),
+			Edit::ReplaceLine(index, line) => map.replace_line(index, line),
+			Edit::MoveLine { from, to } => map.move_line(from, to),
+			Edit::ReplaceServices(line, services) => {
+				map.replace_services(line, services)
+			}
+			Edit::ReplaceStyle(style) => map.replace_style(style),
+			Edit::InsertTransfer(a, b) => map.insert_transfer(a, b),
+			Edit::DeleteTransfer(a, b) => map.delete_transfer(a, b),
+			Edit::Restore(previous) => map.restore(*previous),
+			Edit::Group(edits) => {
+				let mut undo: Vec<_> =
+					edits.into_iter().map(|edit| edit.apply(map)).collect();
+
+				// The last edit made has to be the first one undone
+				undo.reverse();
+
+				Edit::Group(undo)
+			}
+		}
+	}
+
+	/// Which layers of the map view have to be drawn again after this edit's
+	/// counterpart was applied to `map`.
+	pub(crate) fn redraw(&self, map: &Map) -> Redraw {
+		// Markers are stretched across the segments at their stations, and
+		// ticked in their lines' colors
+		let lines = Redraw::SELECTION;
+		// Labels are only shown once for each complex
+		let complexes = Redraw {
+			stations: true,
+			labels: true,
+			..Redraw::NONE
+		};
+
+		match self {
+			Edit::InsertStation(_) | Edit::DeleteStation(_) => complexes,
+			Edit::ReplaceStation(other) => {
+				let station = match map.find_station(other.id) {
+					Some(index) => &map.graph[index],
+					None => return Redraw::ALL,
+				};
+
+				if station.position != other.position {
+					return Redraw::ALL;
+				}
+
+				Redraw {
+					stations: station.marker != other.marker
+						|| station.accessible != other.accessible,
+					labels: station.name != other.name
+						|| station.label != other.label,
+					..Redraw::NONE
+				}
+			}
+			Edit::InsertSegment { .. }
+			| Edit::DeleteSegment(_)
+			| Edit::ReplaceSegment(_)
+			| Edit::InsertLine(..)
+			| Edit::DeleteLine(_)
+			| Edit::MoveLine { .. }
+			| Edit::ReplaceStyle(_) => lines,
+			Edit::ReplaceLine(index, other) => {
+				match map.lines.get(*index as usize) {
+					Some(line) if line.color == other.color => Redraw::NONE,
+					_ => lines,
+				}
+			}
+			Edit::ReplaceServices(..) => Redraw::NONE,
+			Edit::InsertTransfer(..) | Edit::DeleteTransfer(..) => complexes,
+			Edit::Restore(_) => Redraw::ALL,
+			Edit::Group(edits) => edits
+				.iter()
+				.fold(Redraw::NONE, |redraw, edit| redraw | edit.redraw(map)),
+		}
+	}
+}
+
+/// A run of small changes to the same thing, such as typing into a text
+/// field, which are undone all at once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Series {
+	/// Changes to a line's name.
+	Name(LineIndex),
+	/// Changes to a line's code.
+	Code(LineIndex),
+	/// Changes to a line's color.
+	Color(LineIndex),
+	/// Changes to the map's turn radius.
+	TurnRadius,
+	/// Changes to the turn radius of the selected segments.
+	SegmentTurnRadius,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct History {
+	undo: Vec<Edit>,
+	redo: Vec<Edit>,
+	/// Edits being collected into a single undo step, in the order they were
+	/// made.
+	group: Option<Vec<Edit>>,
+	/// The series the last undo step belongs to, if it's still being added
+	/// to.
+	series: Option<Series>,
+}
+
+impl History {
+	/// Records the edit that undoes a change just made.
+	pub(crate) fn record(&mut self, undo: Edit) {
+		match &mut self.group {
+			Some(group) => group.push(undo),
+			None => self.undo.push(undo),
+		}
+
+		self.redo.clear();
+		self.series = None;
+	}
+
+	/// Records the edit that undoes a change in a series. Only the first
+	/// change in a row from the same series is kept, since undoing it
+	/// restores how things were before the whole series.
+	pub(crate) fn record_series(&mut self, undo: Edit, series: Series) {
+		if self.group.is_some() || self.series != Some(series) {
+			self.record(undo);
+			self.series = Some(series);
+		} else {
+			self.redo.clear();
+		}
+	}
+
+	/// Starts collecting edits into a single undo step, until
+	/// [`History::end_group`] is called.
+	pub(crate) fn begin_group(&mut self) {
+		self.end_group();
+		self.series = None;
+		self.group = Some(Vec::new());
+	}
+
+	pub(crate) fn end_group(&mut self) {
+		if let Some(group) = self.group.take() {
+			if !group.is_empty() {
+				// Later changes have to be undone first
+				self.undo
+					.push(Edit::Group(group.into_iter().rev().collect()));
+			}
+		}
+	}
+
+	/// Undoes the last change, returning the edit that redoes it.
+	pub(crate) fn undo(&mut self, map: &mut Map) -> Option<&Edit> {
+		self.end_group();
+		self.series = None;
+
+		let edit = self.undo.pop()?;
+		self.redo.push(edit.apply(map));
+		self.redo.last()
+	}
+
+	/// Redoes the last change undone, returning the edit that undoes it
+	/// again.
+	pub(crate) fn redo(&mut self, map: &mut Map) -> Option<&Edit> {
+		self.end_group();
+		self.series = None;
+
+		let edit = self.redo.pop()?;
+		self.undo.push(edit.apply(map));
+		self.undo.last()
+	}
+
+	pub(crate) fn clear(&mut self) {
+		self.undo.clear();
+		self.redo.clear();
+		self.group = None;
+		self.series = None;
+	}
+}
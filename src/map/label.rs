@@ -0,0 +1,141 @@
+// Copyright (C) 2022  ThatsNoMoon
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of version 3 of the GNU Affero General Public License as published
+// by the Free Software Foundation.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU Affero General Public License
+// for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::{f32::consts::FRAC_1_SQRT_2, fmt};
+
+use iced::{Point, Rectangle, Vector};
+use serde::{Deserialize, Serialize};
+
+use super::{
+	font,
+	render::{LABEL_GAP, LABEL_SIZE, STATION_OUTER_SIZE},
+};
+
+/// Which side of its station a label is drawn on.
+#[derive(
+	Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize,
+)]
+pub(crate) enum Anchor {
+	N,
+	NE,
+	#[default]
+	E,
+	SE,
+	S,
+	SW,
+	W,
+	NW,
+}
+
+impl Anchor {
+	pub(crate) const ALL: [Anchor; 8] = [
+		Anchor::N,
+		Anchor::NE,
+		Anchor::E,
+		Anchor::SE,
+		Anchor::S,
+		Anchor::SW,
+		Anchor::W,
+		Anchor::NW,
+	];
+
+	/// Unit vector pointing from the station towards the label.
+	pub(super) fn direction(self) -> Vector {
+		let d = FRAC_1_SQRT_2;
+
+		match self {
+			Anchor::N => Vector::new(0.0, -1.0),
+			Anchor::NE => Vector::new(d, -d),
+			Anchor::E => Vector::new(1.0, 0.0),
+			Anchor::SE => Vector::new(d, d),
+			Anchor::S => Vector::new(0.0, 1.0),
+			Anchor::SW => Vector::new(-d, d),
+			Anchor::W => Vector::new(-1.0, 0.0),
+			Anchor::NW => Vector::new(-d, -d),
+		}
+	}
+}
+
+impl fmt::Display for Anchor {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let name = match self {
+			Anchor::N => "North",
+			Anchor::NE => "North-east",
+			Anchor::E => "East",
+			Anchor::SE => "South-east",
+			Anchor::S => "South",
+			Anchor::SW => "South-west",
+			Anchor::W => "West",
+			Anchor::NW => "North-west",
+		};
+
+		f.write_str(name)
+	}
+}
+
+/// Where a station's name is drawn.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Label {
+	pub(super) anchor: Anchor,
+	/// Moves the label away from where its anchor would put it.
+	pub(super) offset: Vector,
+	/// Set when the label was placed by hand, so automatic placement leaves
+	/// it alone.
+	pub(super) pinned: bool,
+}
+
+impl Default for Label {
+	fn default() -> Self {
+		Self {
+			anchor: Anchor::default(),
+			offset: Vector::new(0.0, 0.0),
+			pinned: false,
+		}
+	}
+}
+
+impl Label {
+	pub(crate) fn anchor(&self) -> Anchor {
+		self.anchor
+	}
+
+	pub(crate) fn pinned(&self) -> bool {
+		self.pinned
+	}
+
+	/// The area covered by the given name when labelling a station at
+	/// `position`. The label sits just outside the station, extending away
+	/// from it in the direction of the anchor.
+	pub(super) fn rect(&self, position: Point, name: &str) -> Rectangle {
+		let direction = self.anchor.direction();
+		let size = font::measure(name, LABEL_SIZE);
+
+		let point = position
+			+ direction * (STATION_OUTER_SIZE + LABEL_GAP)
+			+ self.offset;
+
+		let x = match direction.x {
+			x if x > 0.0 => point.x,
+			x if x < 0.0 => point.x - size.width,
+			_ => point.x - size.width / 2.0,
+		};
+		let y = match direction.y {
+			y if y > 0.0 => point.y,
+			y if y < 0.0 => point.y - size.height,
+			_ => point.y - size.height / 2.0,
+		};
+
+		Rectangle::new(Point::new(x, y), size)
+	}
+}
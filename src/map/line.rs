@@ -0,0 +1,313 @@
+// Copyright (C) 2022  ThatsNoMoon
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of version 3 of the GNU Affero General Public License as published
+// by the Free Software Foundation.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU Affero General Public License
+// for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use iced::Color;
+
+use super::{Edit, LineIndex, Link, Map, Service};
+use crate::color::ColorExt;
+
+/// Colors offered for lines, and given to new ones.
+pub(crate) const PALETTE: [u32; 12] = [
+	0x33bbff, 0x3cbe3c, 0xff714d, 0xbf60bf, 0xff9600, 0xffd700, 0xe0457b,
+	0x00a99d, 0x2e5cb8, 0xa3c940, 0x8d6e63, 0x9e9e9e,
+];
+
+/// How many lines new maps start with.
+const DEFAULT_LINES: usize = 6;
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Line {
+	pub(super) name: String,
+	/// A short name for the line, such as a letter or number.
+	pub(super) code: String,
+	pub(super) color: Color,
+	/// The ways the line runs, kept up to date as its segments change.
+	pub(super) services: Vec<Service>,
+}
+
+impl Line {
+	pub(super) fn new(color: Color) -> Self {
+		Self {
+			name: String::new(),
+			code: String::new(),
+			color,
+			services: Vec::new(),
+		}
+	}
+
+	pub(crate) fn name(&self) -> &str {
+		&self.name
+	}
+
+	pub(crate) fn code(&self) -> &str {
+		&self.code
+	}
+
+	pub(crate) fn color(&self) -> Color {
+		self.color
+	}
+}
+
+pub(super) fn default_lines() -> Vec<Line> {
+	PALETTE[..DEFAULT_LINES]
+		.iter()
+		.map(|&c| Line::new(Color::from_rgb32(c)))
+		.collect()
+}
+
+impl Map {
+	pub(crate) fn lines(&self) -> &[Line] {
+		&self.lines
+	}
+
+	/// How many segments belong to the given line.
+	pub(crate) fn line_segments(&self, line: LineIndex) -> usize {
+		self.graph
+			.edge_weights()
+			.filter_map(Link::segment)
+			.filter(|segment| segment.line == line)
+			.count()
+	}
+
+	/// Adds a line with the first color from the palette that isn't already
+	/// used, returning the edit that undoes it.
+	pub(crate) fn add_line(&mut self) -> Edit {
+		let used = |&&c: &&u32| {
+			self.lines.iter().any(|line| line.color.to_rgb32() == c)
+		};
+		let color = PALETTE
+			.iter()
+			.find(|c| !used(c))
+			.copied()
+			.unwrap_or(PALETTE[self.lines.len() % PALETTE.len()]);
+
+		self.insert_line(
+			self.lines.len() as LineIndex,
+			Line::new(Color::from_rgb32(color)),
+		)
+	}
+
+	pub(crate) fn rename_line(
+		&mut self,
+		index: LineIndex,
+		name: String,
+	) -> Edit {
+		let line = Line {
+			name,
+			..self.lines[index as usize].clone()
+		};
+
+		self.replace_line(index, line)
+	}
+
+	pub(crate) fn set_line_code(
+		&mut self,
+		index: LineIndex,
+		code: String,
+	) -> Edit {
+		let line = Line {
+			code,
+			..self.lines[index as usize].clone()
+		};
+
+		self.replace_line(index, line)
+	}
+
+	pub(crate) fn recolor_line(
+		&mut self,
+		index: LineIndex,
+		color: Color,
+	) -> Edit {
+		let line = Line {
+			color,
+			..self.lines[index as usize].clone()
+		};
+
+		self.replace_line(index, line)
+	}
+
+	/// Removes a line, moving its segments to another line if one is given,
+	/// or removing them otherwise. Moving them to the line itself removes
+	/// them too. Returns the edit that undoes it.
+	pub(crate) fn remove_line(
+		&mut self,
+		index: LineIndex,
+		reassign: Option<LineIndex>,
+	) -> Edit {
+		let reassign = reassign.filter(|&target| target != index);
+
+		let segments: Vec<_> = self
+			.segment_indices()
+			.filter(|&segment| self.segment(segment).line == index)
+			.map(|segment| {
+				// The segment is recreated on the new line going the same way
+				let (start, end) = self
+					.graph
+					.edge_endpoints(segment)
+					.expect("Edge index from iteration not in graph");
+				(
+					self.graph[start].id,
+					self.graph[end].id,
+					self.segment(segment).clone(),
+				)
+			})
+			.collect();
+
+		let mut undo = Vec::new();
+
+		for (start, end, mut segment) in segments {
+			undo.push(self.delete_segment(segment.id));
+
+			let target = match reassign {
+				Some(target) => target,
+				None => continue,
+			};
+
+			// Lines only connect two stations once
+			let (a, b) = (self.station_index(start), self.station_index(end));
+			if self
+				.segments_between(a, b)
+				.any(|(_, segment)| segment.line == target)
+			{
+				continue;
+			}
+
+			segment.line = target;
+			undo.push(self.insert_segment(start, end, segment));
+		}
+
+		undo.push(self.delete_line(index));
+
+		undo.reverse();
+		Edit::Group(undo)
+	}
+
+	/// Moves a line to a new position in the list of lines, returning the edit
+	/// that undoes it.
+	pub(crate) fn move_line(&mut self, from: LineIndex, to: LineIndex) -> Edit {
+		let line = self.lines.remove(from as usize);
+		self.lines.insert(to as usize, line);
+
+		self.renumber_lines(|l| {
+			if l == from {
+				to
+			} else if from < to && (from + 1..=to).contains(&l) {
+				l - 1
+			} else if to < from && (to..from).contains(&l) {
+				l + 1
+			} else {
+				l
+			}
+		});
+
+		Edit::MoveLine { from: to, to: from }
+	}
+
+	/// The line most like one from another map: one that looks the same in
+	/// every way, or else one with the same name, or else one with the same
+	/// color.
+	pub(super) fn matching_line(&self, line: &Line) -> Option<LineIndex> {
+		let find = |f: &dyn Fn(&Line) -> bool| {
+			self.lines.iter().position(f).map(|i| i as LineIndex)
+		};
+
+		find(&|l| {
+			(&l.name, &l.code, l.color) == (&line.name, &line.code, line.color)
+		})
+		.or_else(|| find(&|l| !line.name.is_empty() && l.name == line.name))
+		.or_else(|| find(&|l| l.color == line.color))
+	}
+
+	pub(super) fn insert_line(&mut self, index: LineIndex, line: Line) -> Edit {
+		self.renumber_lines(|l| if l >= index { l + 1 } else { l });
+		self.lines.insert(index as usize, line);
+
+		Edit::DeleteLine(index)
+	}
+
+	/// Removes a line, which mustn't have any segments left.
+	pub(super) fn delete_line(&mut self, index: LineIndex) -> Edit {
+		debug_assert_eq!(self.line_segments(index), 0);
+
+		let line = self.lines.remove(index as usize);
+		self.renumber_lines(|l| if l > index { l - 1 } else { l });
+
+		Edit::InsertLine(index, line)
+	}
+
+	pub(super) fn replace_line(
+		&mut self,
+		index: LineIndex,
+		line: Line,
+	) -> Edit {
+		let previous = std::mem::replace(&mut self.lines[index as usize], line);
+
+		Edit::ReplaceLine(index, previous)
+	}
+
+	fn renumber_lines(&mut self, f: impl Fn(LineIndex) -> LineIndex) {
+		for segment in
+			self.graph.edge_weights_mut().filter_map(Link::segment_mut)
+		{
+			segment.line = f(segment.line);
+		}
+		self.forget_bundles();
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::map::tests::TestMap;
+
+	/// Three stations, with a segment of line 0 then one of line 1.
+	fn sample() -> Map {
+		TestMap::new(&[(0.0, 0.0), (100.0, 0.0), (200.0, 0.0)])
+			.line(0, &[0, 1])
+			.line(1, &[1, 2])
+			.build()
+	}
+
+	#[test]
+	fn delete_after_move() {
+		let mut map = sample();
+		let lines = map.lines.clone();
+
+		// Line 1 was picked to take line 0's segments, then moved up, so
+		// it's now the line being deleted
+		let _ = map.move_line(1, 0);
+		let undo = map.remove_line(0, Some(0));
+
+		assert_eq!(map.lines.len(), lines.len() - 1);
+		assert_eq!(map.segment_indices().count(), 1);
+		assert_eq!(map.line_segments(0), 1);
+		assert_eq!(map.lines[0], lines[0]);
+
+		let _ = undo.apply(&mut map);
+		assert_eq!(map.line_segments(0), 1);
+		assert_eq!(map.line_segments(1), 1);
+		assert_eq!(map.lines[0].color, lines[1].color);
+	}
+
+	#[test]
+	fn reassign_after_move() {
+		let mut map = sample();
+
+		let _ = map.move_line(1, 0);
+		let _ = map.remove_line(0, Some(1));
+
+		assert_eq!(map.segment_indices().count(), 2);
+		assert_eq!(map.line_segments(0), 2);
+	}
+}
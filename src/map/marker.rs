@@ -0,0 +1,128 @@
+// Copyright (C) 2022  ThatsNoMoon
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of version 3 of the GNU Affero General Public License as published
+// by the Free Software Foundation.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU Affero General Public License
+// for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use super::{Edit, LineIndex, Map, StationId, StationIndex};
+
+/// How a station is marked on the map.
+#[derive(
+	Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize,
+)]
+pub(crate) enum Marker {
+	/// Picked from the lines at the station; see [`Map::station_marker`].
+	#[default]
+	Auto,
+	/// A ring stretched across every line at the station.
+	Interchange,
+	/// A short tick on one side of the line, in the line's color.
+	Tick,
+	/// A bar across the end of the line, in the line's color.
+	Terminus,
+	/// An open circle, for stations trains only stop at on request.
+	Request,
+}
+
+impl Marker {
+	pub(crate) const ALL: [Marker; 5] = [
+		Marker::Auto,
+		Marker::Interchange,
+		Marker::Tick,
+		Marker::Terminus,
+		Marker::Request,
+	];
+}
+
+impl fmt::Display for Marker {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let name = match self {
+			Marker::Auto => "Automatic",
+			Marker::Interchange => "Interchange",
+			Marker::Tick => "Tick",
+			Marker::Terminus => "Terminus",
+			Marker::Request => "Request stop",
+		};
+
+		f.write_str(name)
+	}
+}
+
+impl Map {
+	/// How a station is drawn. Stations on more than one line, or none, and
+	/// stations in a complex are interchanges; otherwise a station is a
+	/// terminus where its line ends, and a tick where the line runs through.
+	pub(super) fn station_marker(&self, index: StationIndex) -> Marker {
+		let station = &self.graph[index];
+		if station.marker != Marker::Auto {
+			return station.marker;
+		}
+		if self.complex(index).len() > 1 {
+			return Marker::Interchange;
+		}
+
+		let mut lines =
+			self.segments_at(index).map(|(.., segment)| segment.line);
+		let line = match lines.next() {
+			Some(line) => line,
+			None => return Marker::Interchange,
+		};
+		if lines.any(|other| other != line) {
+			return Marker::Interchange;
+		}
+
+		match self.segments_at(index).count() {
+			1 => Marker::Terminus,
+			2 => Marker::Tick,
+			// Where a line branches
+			_ => Marker::Interchange,
+		}
+	}
+
+	/// The line a station's tick or bar is drawn in the color of.
+	pub(super) fn station_line(
+		&self,
+		index: StationIndex,
+	) -> Option<LineIndex> {
+		self.segments_at(index)
+			.map(|(.., segment)| segment.line)
+			.min()
+	}
+
+	/// Sets how a station is marked, returning the edit that undoes it.
+	pub(crate) fn set_station_marker(
+		&mut self,
+		id: StationId,
+		marker: Marker,
+	) -> Edit {
+		let mut station = self.graph[self.station_index(id)].clone();
+		station.marker = marker;
+
+		self.replace_station(station)
+	}
+
+	/// Sets whether a station is marked as step-free, returning the edit that
+	/// undoes it.
+	pub(crate) fn set_station_accessible(
+		&mut self,
+		id: StationId,
+		accessible: bool,
+	) -> Edit {
+		let mut station = self.graph[self.station_index(id)].clone();
+		station.accessible = accessible;
+
+		self.replace_station(station)
+	}
+}
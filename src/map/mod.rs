@@ -12,154 +12,1005 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
+mod bundle;
+mod curve;
+mod export;
+mod font;
+mod geometry;
+mod history;
+mod label;
+mod line;
+mod marker;
+mod placement;
+mod project;
+mod render;
+mod selection;
+mod service;
+mod spatial;
+mod style;
+mod transfer;
 mod view;
+mod waypoint;
 
-use std::collections::HashMap;
+use std::{cell::OnceCell, fmt};
 
-use iced::{
-	pure::widget::{canvas::Program, Canvas},
-	Color, Point,
+use iced::{pure::Element, Point, Vector};
+use ordered_float::NotNan;
+use petgraph::{
+	graph::{EdgeIndex, NodeIndex},
+	visit::EdgeRef,
+	Undirected,
 };
-use petgraph::{graph::NodeIndex, visit::EdgeRef, Undirected};
+use serde::{Deserialize, Serialize};
 
-use self::view::MapView;
-use crate::{color::ColorExt, EditMode, Message};
+use self::{
+	bundle::Bundles,
+	geometry::{closest_on_line, distance_to_line, magnitude},
+	spatial::SpatialIndex,
+	view::MapView,
+};
+pub(crate) use self::{
+	export::{Background, Error as ExportError, ExportFormat, ExportOptions},
+	history::{Edit, History, Series},
+	label::{Anchor, Label},
+	line::{Line, PALETTE},
+	marker::Marker,
+	project::{Error as ProjectError, EXTENSION},
+	selection::Selection,
+	service::Service,
+	style::{Style, MAX_TURN_RADIUS},
+	view::{Redraw, Revision},
+};
+use crate::{EditMode, Message};
 
 type Index = u16;
 
 pub(crate) type StationIndex = NodeIndex<Index>;
 pub(crate) type LineIndex = Index;
 
-type Graph = petgraph::Graph<Station, Segment, Undirected, Index>;
+type Graph = petgraph::Graph<Station, Link, Undirected, Index>;
+
+/// Identifies a station independently of its position in the graph, which
+/// changes whenever another station is removed.
+#[derive(
+	Debug,
+	Clone,
+	Copy,
+	PartialEq,
+	Eq,
+	Hash,
+	PartialOrd,
+	Ord,
+	Serialize,
+	Deserialize,
+)]
+#[serde(transparent)]
+pub(crate) struct StationId(u32);
+
+/// Identifies a segment independently of its position in the graph, like
+/// [`StationId`]. These aren't saved, and are reassigned on load.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub(crate) struct SegmentId(u32);
 
 #[derive(Debug, Clone)]
 pub(crate) struct Map {
 	graph: Graph,
 	lines: Vec<Line>,
+	style: Style,
+	/// Where everything in the graph is, to find it quickly.
+	spatial: SpatialIndex,
+	/// Worked out when they're first needed after the segments change.
+	bundles: OnceCell<Bundles>,
+	next_station_id: u32,
+	next_segment_id: u32,
 }
 
 impl Default for Map {
 	fn default() -> Self {
 		Self {
 			graph: Graph::with_capacity(0, 0),
-			lines: [0x33bbff, 0x3cbe3c, 0xff714d, 0xbf60bf, 0xff9600, 0xffd700]
-				.into_iter()
-				.map(|c| Line {
-					color: Color::from_rgb32(c),
-				})
-				.collect(),
+			lines: line::default_lines(),
+			style: Style::default(),
+			spatial: SpatialIndex::default(),
+			bundles: OnceCell::new(),
+			next_station_id: 0,
+			next_segment_id: 0,
 		}
 	}
 }
 
 impl Map {
-	pub(crate) fn add_station(&mut self, station: Station) {
-		self.graph.add_node(station);
+	/// Adds a station, returning the edit that undoes it.
+	pub(crate) fn add_station(&mut self, position: Point) -> Edit {
+		let id = StationId(self.next_station_id);
+		self.next_station_id += 1;
+
+		self.insert_station(Station {
+			id,
+			position,
+			name: String::new(),
+			label: Label::default(),
+			marker: Marker::default(),
+			accessible: false,
+		})
 	}
 
+	/// Adds a segment, returning the edit that undoes it.
 	pub(crate) fn add_segment(
 		&mut self,
 		line: LineIndex,
 		start: StationIndex,
 		end: StationIndex,
-	) {
-		self.graph.add_edge(
-			start,
-			end,
-			Segment {
-				line,
-				interpolation: Interpolation::Auto(
-					InterpolationDirection::Auto,
-				),
-			},
-		);
+	) -> Edit {
+		let segment = self.new_segment(line, Interpolation::default());
+		let (start, end) = (self.graph[start].id, self.graph[end].id);
+
+		self.insert_segment(start, end, segment)
 	}
 
-	pub(crate) fn remove_station(&mut self, index: StationIndex) {
-		let mut to_rejoin = HashMap::new();
+	/// Removes a station, joining up the stations on either side of it
+	/// wherever one of a line's services runs through it, so branches stay
+	/// connected to their trunk and loops stay closed. Returns the edit that
+	/// undoes this, restoring the removed segments and removing the joining
+	/// ones.
+	pub(crate) fn remove_station(&mut self, index: StationIndex) -> Edit {
+		let id = self.graph[index].id;
 
-		for edge in self.graph.edges(index) {
-			let line = edge.weight().line;
-			let interpolation = edge.weight().interpolation;
-			let endpoint = if edge.source() == index {
-				edge.target()
-			} else {
-				edge.source()
-			};
+		let mut joins = Vec::new();
+		let mut services = Vec::new();
 
-			match to_rejoin.get(&line).copied() {
-				None => {
-					to_rejoin.insert(line, Ok((endpoint, None, interpolation)));
-				}
-				Some(Ok((a, None, i))) => {
-					to_rejoin.insert(line, Ok((a, Some(endpoint), i)));
+		for line in 0..self.lines.len() as LineIndex {
+			let (remaining, pairs) = self.services_without(line, id);
+
+			for (a, b) in pairs {
+				let (a_index, b_index) =
+					(self.station_index(a), self.station_index(b));
+				if self.line_segment(line, a_index, b_index).is_some() {
+					continue;
 				}
-				Some(Ok((_, Some(_), _))) => {
-					to_rejoin.insert(line, Err(()));
+
+				if let (Some(a_edge), Some(b_edge)) = (
+					self.line_segment(line, a_index, index),
+					self.line_segment(line, index, b_index),
+				) {
+					let interpolation =
+						self.joined_interpolation(index, a_edge, b_edge);
+					joins.push((line, a, b, interpolation));
 				}
-				Some(Err(())) => (),
+			}
+
+			if remaining != self.services(line) {
+				services.push((line, remaining));
 			}
 		}
 
-		self.graph.remove_node(index);
+		let mut undo = vec![self.delete_station(id)];
 
-		for (line, rejoin) in to_rejoin {
-			let (a, b, interpolation) = match rejoin {
-				Ok((a, Some(b), i)) => (a, b, i),
-				_ => continue,
-			};
+		for (line, a, b, interpolation) in joins {
+			let segment = self.new_segment(line, interpolation);
+			undo.push(self.insert_segment(a, b, segment));
+		}
 
-			self.graph.add_edge(
-				a,
-				b,
-				Segment {
-					line,
-					interpolation,
-				},
+		// Joining segments one by one can't tell branches from lines carrying
+		// on, so the services are set as they should be afterwards
+		for (line, remaining) in services {
+			undo.push(self.replace_services(line, remaining));
+		}
+
+		undo.reverse();
+		Edit::Group(undo)
+	}
+
+	/// The segment of a line between two stations, if there is one.
+	fn line_segment(
+		&self,
+		line: LineIndex,
+		a: StationIndex,
+		b: StationIndex,
+	) -> Option<EdgeIndex<Index>> {
+		self.segments_between(a, b)
+			.find(|(_, segment)| segment.line == line)
+			.map(|(index, _)| index)
+	}
+
+	/// How to draw a segment joining the far ends of segments `a` and `b`,
+	/// which meet at `station`. If either has waypoints, the joined segment
+	/// keeps them, with another where the station was, and it's only curved
+	/// if both were.
+	fn joined_interpolation(
+		&self,
+		station: StationIndex,
+		a: EdgeIndex<Index>,
+		b: EdgeIndex<Index>,
+	) -> Interpolation {
+		let graph = &self.graph;
+
+		let is_auto = |edge: EdgeIndex<Index>| {
+			matches!(self.segment(edge).interpolation, Interpolation::Auto(..))
+		};
+		if is_auto(a) && is_auto(b) {
+			return self.segment(a).interpolation.clone();
+		}
+
+		let curve = self.segment(a).interpolation.is_curve()
+			&& self.segment(b).interpolation.is_curve();
+
+		// The corners of a segment, listed going towards the station
+		let corners = |edge: EdgeIndex<Index>| {
+			let (start, end) = graph
+				.edge_endpoints(edge)
+				.expect("Edge index from iteration not in graph");
+			let path = bundle::path(
+				graph[start].position,
+				graph[end].position,
+				&self.segment(edge).interpolation,
 			);
+
+			let mut corners = path[1..path.len() - 1].to_vec();
+			if start == station {
+				corners.reverse();
+			}
+			corners
+		};
+
+		let mut points = corners(a);
+		points.push(graph[station].position);
+		points.extend(corners(b).into_iter().rev());
+
+		if curve {
+			Interpolation::Curve(points)
+		} else {
+			Interpolation::Waypoints(points)
 		}
 	}
 
-	pub(crate) fn clear(&mut self) {
+	/// Removes a segment, returning the edit that undoes it.
+	pub(crate) fn remove_segment(&mut self, id: SegmentId) -> Edit {
+		self.delete_segment(id)
+	}
+
+	/// Adds a station on a segment's path, as close to `at` as it can be,
+	/// and splits the segment in two there. Each half keeps the part of the
+	/// path it covers. The station goes on the grid if the path passes
+	/// through a grid point there. Returns the edit that undoes this.
+	pub(crate) fn split_segment(&mut self, id: SegmentId, at: Point) -> Edit {
+		let index = self.segment_index(id);
+		let (start, end) = self
+			.graph
+			.edge_endpoints(index)
+			.expect("Segment index from lookup not in graph");
+
+		let drawn = render::segment_shape(self, index, 0.0).flatten();
+		let closest = |p: Point| {
+			drawn
+				.iter()
+				.flat_map(|points| points.windows(2))
+				.map(|leg| closest_on_line(p, leg[0], leg[1]))
+				.min_by_key(|&q| {
+					NotNan::new(magnitude(q - p)).expect("Distance is NaN")
+				})
+				.unwrap_or(p)
+		};
+
+		let on_path = closest(at);
+		let snapped = render::snap(on_path);
+		let position = if magnitude(closest(snapped) - snapped) < 0.5 {
+			snapped
+		} else {
+			on_path
+		};
+
+		let (from, to) = (self.graph[start].position, self.graph[end].position);
+		let (start, end) = (self.graph[start].id, self.graph[end].id);
+		let segment = self.segment(index).clone();
+
+		let path = bundle::path(from, to, &segment.interpolation);
+		let leg = path
+			.windows(2)
+			.map(|leg| {
+				NotNan::new(distance_to_line(position, leg[0], leg[1]))
+					.expect("Distance is NaN")
+			})
+			.enumerate()
+			.min_by_key(|&(_, d)| d)
+			.map_or(0, |(i, _)| i);
+
+		let first = split_interpolation(
+			&segment.interpolation,
+			from,
+			path[1..=leg].to_vec(),
+			position,
+		);
+		let second = split_interpolation(
+			&segment.interpolation,
+			position,
+			path[leg + 1..path.len() - 1].to_vec(),
+			to,
+		);
+
+		let mut undo = vec![self.delete_segment(id)];
+
+		let station = StationId(self.next_station_id);
+		self.next_station_id += 1;
+		undo.push(self.insert_station(Station {
+			id: station,
+			position,
+			name: String::new(),
+			label: Label::default(),
+			marker: Marker::default(),
+			accessible: false,
+		}));
+
+		// The first half keeps the original's ID
+		let first = Segment {
+			interpolation: first,
+			..segment.clone()
+		};
+		undo.push(self.insert_segment(start, station, first));
+
+		let mut second_half = self.new_segment(segment.line, second);
+		second_half.turn_radius = segment.turn_radius;
+		undo.push(self.insert_segment(station, end, second_half));
+
+		undo.reverse();
+		Edit::Group(undo)
+	}
+
+	/// Renames a station, returning the edit that undoes it.
+	pub(crate) fn rename_station(
+		&mut self,
+		id: StationId,
+		name: String,
+	) -> Edit {
+		let station = Station {
+			name,
+			..self.graph[self.station_index(id)].clone()
+		};
+
+		self.replace_station(station)
+	}
+
+	/// Moves a station's label to the other side of the given anchor,
+	/// discarding its offset and pinning it there. Returns the edit that
+	/// undoes it.
+	pub(crate) fn set_label_anchor(
+		&mut self,
+		id: StationId,
+		anchor: Anchor,
+	) -> Edit {
+		let station = Station {
+			label: Label {
+				anchor,
+				offset: Vector::new(0.0, 0.0),
+				pinned: true,
+			},
+			..self.graph[self.station_index(id)].clone()
+		};
+
+		self.replace_station(station)
+	}
+
+	/// Moves a station's label away from its anchor and pins it there,
+	/// returning the edit that undoes it.
+	pub(crate) fn move_label(
+		&mut self,
+		index: StationIndex,
+		offset: Vector,
+	) -> Edit {
+		let mut station = self.graph[index].clone();
+		station.label.offset = offset;
+		station.label.pinned = true;
+
+		self.replace_station(station)
+	}
+
+	/// Moves a station, taking its label and segments with it, and returns
+	/// the edit that undoes it.
+	pub(crate) fn move_station(
+		&mut self,
+		index: StationIndex,
+		position: Point,
+	) -> Edit {
+		let mut station = self.graph[index].clone();
+		station.position = position;
+
+		self.replace_station(station)
+	}
+
+	/// Sets whether a station's label is left alone by
+	/// [`Map::place_labels`], returning the edit that undoes it.
+	pub(crate) fn pin_label(&mut self, id: StationId, pinned: bool) -> Edit {
+		let mut station = self.graph[self.station_index(id)].clone();
+		station.label.pinned = pinned;
+
+		self.replace_station(station)
+	}
+
+	/// Removes every station and segment, returning the edit that undoes it.
+	pub(crate) fn clear(&mut self) -> Edit {
+		let previous = self.clone();
 		self.graph.clear();
+		self.spatial = SpatialIndex::default();
+		for line in &mut self.lines {
+			line.services.clear();
+		}
+		self.forget_bundles();
+
+		Edit::Restore(Box::new(previous))
+	}
+
+	fn new_segment(
+		&mut self,
+		line: LineIndex,
+		interpolation: Interpolation,
+	) -> Segment {
+		let id = SegmentId(self.next_segment_id);
+		self.next_segment_id += 1;
+
+		Segment {
+			id,
+			line,
+			interpolation,
+			turn_radius: None,
+		}
+	}
+
+	fn insert_station(&mut self, station: Station) -> Edit {
+		let id = station.id;
+		let index = self.graph.add_node(station);
+		self.index_station(index);
+
+		Edit::DeleteStation(id)
+	}
+
+	/// Replaces the station with the same ID, keeping its segments.
+	fn replace_station(&mut self, station: Station) -> Edit {
+		let index = self.station_index(station.id);
+		let previous = std::mem::replace(&mut self.graph[index], station);
+
+		self.index_station(index);
+		if previous.position != self.graph[index].position {
+			self.index_around(index);
+			self.forget_bundles();
+		}
+
+		Edit::ReplaceStation(previous)
+	}
+
+	/// Removes a station along with its segments and transfers.
+	fn delete_station(&mut self, id: StationId) -> Edit {
+		let index = self.station_index(id);
+
+		let segments: Vec<_> = self
+			.segments_at(index)
+			.map(|(_, _, segment)| segment.id)
+			.collect();
+		let mut undo: Vec<_> = segments
+			.into_iter()
+			.map(|segment| self.delete_segment(segment))
+			.collect();
+
+		let transfers: Vec<_> = self
+			.transfers_at(index)
+			.map(|other| self.graph[other].id)
+			.collect();
+		for other in transfers {
+			undo.push(self.delete_transfer(id, other));
+		}
+
+		let index = self.station_index(id);
+		let station = self
+			.graph
+			.remove_node(index)
+			.expect("Station index from lookup not in graph");
+		self.unindex_station(index, id);
+		self.forget_bundles();
+
+		undo.push(Edit::InsertStation(station));
+
+		undo.reverse();
+		Edit::Group(undo)
+	}
+
+	fn insert_segment(
+		&mut self,
+		start: StationId,
+		end: StationId,
+		segment: Segment,
+	) -> Edit {
+		let (id, line) = (segment.id, segment.line);
+		let services = self.connect_services(line, start, end);
+
+		let (start, end) = (self.station_index(start), self.station_index(end));
+		self.graph.add_edge(start, end, Link::Segment(segment));
+		self.index_around(start);
+		self.index_around(end);
+		self.forget_bundles();
+
+		// Services are put back exactly as they were, after removing the
+		// segment has updated them as best it can
+		Edit::Group(vec![
+			Edit::DeleteSegment(id),
+			Edit::ReplaceServices(line, services),
+		])
+	}
+
+	fn delete_segment(&mut self, id: SegmentId) -> Edit {
+		let index = self.segment_index(id);
+		let (start_index, end_index) = self
+			.graph
+			.edge_endpoints(index)
+			.expect("Segment index from lookup not in graph");
+		let (start, end) =
+			(self.graph[start_index].id, self.graph[end_index].id);
+
+		let segment = match self.graph.remove_edge(index) {
+			Some(Link::Segment(segment)) => segment,
+			_ => panic!("Segment index from lookup not in graph"),
+		};
+		self.unindex_edge(index, Some(id));
+		self.index_around(start_index);
+		self.index_around(end_index);
+		self.forget_bundles();
+		let line = segment.line;
+		let services = self.disconnect_services(line, start, end);
+
+		Edit::Group(vec![
+			Edit::InsertSegment {
+				start,
+				end,
+				segment,
+			},
+			Edit::ReplaceServices(line, services),
+		])
+	}
+
+	fn replace_segment(&mut self, segment: Segment) -> Edit {
+		let index = self.segment_index(segment.id);
+		let previous = std::mem::replace(self.segment_mut(index), segment);
+
+		let (start, end) = self
+			.graph
+			.edge_endpoints(index)
+			.expect("Segment index from lookup not in graph");
+		self.index_around(start);
+		self.index_around(end);
+		self.forget_bundles();
+
+		Edit::ReplaceSegment(previous)
+	}
+
+	fn restore(&mut self, map: Map) -> Edit {
+		Edit::Restore(Box::new(std::mem::replace(self, map)))
+	}
+
+	pub(crate) fn station(&self, index: StationIndex) -> &Station {
+		&self.graph[index]
+	}
+
+	/// Finds the current index of a station, if it still exists.
+	pub(crate) fn find_station(&self, id: StationId) -> Option<StationIndex> {
+		self.spatial.station(id)
+	}
+
+	/// Finds the current index of a station.
+	///
+	/// # Panics
+	/// If there is no such station. IDs are only kept where they must stay
+	/// valid, such as in the edit history.
+	fn station_index(&self, id: StationId) -> StationIndex {
+		self.find_station(id).expect("Station ID not in graph")
+	}
+
+	/// Finds the current index of a segment, if it still exists.
+	fn find_segment(&self, id: SegmentId) -> Option<EdgeIndex<Index>> {
+		self.spatial.segment(id)
+	}
+
+	/// Finds the current index of a segment.
+	///
+	/// # Panics
+	/// If there is no such segment, like [`Map::station_index`].
+	fn segment_index(&self, id: SegmentId) -> EdgeIndex<Index> {
+		self.find_segment(id).expect("Segment ID not in graph")
+	}
+
+	/// The segment at an index in the graph.
+	///
+	/// # Panics
+	/// If the index is a transfer's. Indices are only looked up like this
+	/// where they're known to be segments.
+	fn segment(&self, index: EdgeIndex<Index>) -> &Segment {
+		self.graph[index]
+			.segment()
+			.expect("Edge index is a transfer, not a segment")
+	}
+
+	/// The segment at an index in the graph, like [`Map::segment`].
+	fn segment_mut(&mut self, index: EdgeIndex<Index>) -> &mut Segment {
+		self.graph[index]
+			.segment_mut()
+			.expect("Edge index is a transfer, not a segment")
+	}
+
+	/// The index of every segment, leaving out transfers.
+	fn segment_indices(&self) -> impl Iterator<Item = EdgeIndex<Index>> + '_ {
+		self.graph
+			.edge_indices()
+			.filter(|&index| self.graph[index].segment().is_some())
+	}
+
+	/// The segments at a station, along with the station at the other end of
+	/// each.
+	fn segments_at(
+		&self,
+		station: StationIndex,
+	) -> impl Iterator<Item = (EdgeIndex<Index>, StationIndex, &Segment)> + '_
+	{
+		self.graph.edges(station).filter_map(move |edge| {
+			let other = if edge.source() == station {
+				edge.target()
+			} else {
+				edge.source()
+			};
+
+			Some((edge.id(), other, edge.weight().segment()?))
+		})
+	}
+
+	/// The segments between two stations.
+	fn segments_between(
+		&self,
+		a: StationIndex,
+		b: StationIndex,
+	) -> impl Iterator<Item = (EdgeIndex<Index>, &Segment)> + '_ {
+		self.graph
+			.edges_connecting(a, b)
+			.filter_map(|edge| Some((edge.id(), edge.weight().segment()?)))
+	}
+
+	/// The segment of the same line that continues on from `segment` at
+	/// `station`, if there's exactly one, and the station it leaves from.
+	/// Lines carry on through complexes, so that can be any station in the
+	/// same complex.
+	fn continuing(
+		&self,
+		segment: EdgeIndex<Index>,
+		station: StationIndex,
+	) -> Option<(EdgeIndex<Index>, StationIndex)> {
+		let line = self.segment(segment).line;
+
+		let mut next = self.complex(station).into_iter().flat_map(|from| {
+			self.segments_at(from)
+				.filter(|&(index, _, next)| {
+					index != segment && next.line == line
+				})
+				.map(move |(index, ..)| (index, from))
+		});
+
+		match (next.next(), next.next()) {
+			(Some(next), None) => Some(next),
+			_ => None,
+		}
 	}
 }
 
 #[derive(Debug, Clone)]
 pub(crate) struct Station {
+	id: StationId,
 	position: Point,
+	name: String,
+	label: Label,
+	marker: Marker,
+	/// Whether the station has step-free access, shown with a badge.
+	accessible: bool,
 }
 
+impl Station {
+	pub(crate) fn id(&self) -> StationId {
+		self.id
+	}
+
+	pub(crate) fn name(&self) -> &str {
+		&self.name
+	}
+
+	pub(crate) fn label(&self) -> Label {
+		self.label
+	}
+
+	pub(crate) fn marker(&self) -> Marker {
+		self.marker
+	}
+
+	pub(crate) fn accessible(&self) -> bool {
+		self.accessible
+	}
+}
+
+/// What joins two stations in the graph.
 #[derive(Debug, Clone)]
-struct Line {
-	color: Color,
+enum Link {
+	Segment(Segment),
+	/// A passageway between two stations, which makes them part of the same
+	/// complex.
+	Transfer,
+}
+
+impl Link {
+	fn segment(&self) -> Option<&Segment> {
+		match self {
+			Link::Segment(segment) => Some(segment),
+			Link::Transfer => None,
+		}
+	}
+
+	fn segment_mut(&mut self) -> Option<&mut Segment> {
+		match self {
+			Link::Segment(segment) => Some(segment),
+			Link::Transfer => None,
+		}
+	}
 }
 
 #[derive(Debug, Clone)]
-struct Segment {
+pub(crate) struct Segment {
+	id: SegmentId,
 	line: LineIndex,
 	interpolation: Interpolation,
+	/// Radius of the rounding at the segment's corners, if it's not the
+	/// map's.
+	turn_radius: Option<f32>,
+}
+
+impl Segment {
+	fn turn_radius(&self, style: &Style) -> f32 {
+		self.turn_radius.unwrap_or(style.turn_radius)
+	}
 }
 
-#[derive(Debug, Clone, Copy)]
-enum Interpolation {
-	Auto(InterpolationDirection),
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) enum Interpolation {
+	/// A single bend, worked out from where the stations are.
+	Auto(InterpolationDirection, #[serde(default)] Bend),
+	/// Straight lines through each of the points in turn, from the start of
+	/// the segment to the end.
+	Waypoints(#[serde(with = "project::points")] Vec<Point>),
+	/// A smooth curve through each of the points in turn, which carries on
+	/// smoothly into the segments on either side on the same line.
+	Curve(#[serde(with = "project::points")] Vec<Point>),
 }
 
-#[derive(Debug, Clone, Copy)]
-enum InterpolationDirection {
+impl Interpolation {
+	/// Which way the segment bends, if it's worked out automatically.
+	pub(crate) fn direction(&self) -> Option<InterpolationDirection> {
+		match self {
+			Self::Auto(direction, _) => Some(*direction),
+			Self::Waypoints(_) | Self::Curve(_) => None,
+		}
+	}
+
+	pub(crate) fn is_curve(&self) -> bool {
+		matches!(self, Self::Curve(_))
+	}
+
+	/// The points the segment is drawn through, unless it bends
+	/// automatically.
+	fn waypoints_mut(&mut self) -> Option<&mut Vec<Point>> {
+		match self {
+			Self::Auto(..) => None,
+			Self::Waypoints(points) | Self::Curve(points) => Some(points),
+		}
+	}
+
+	/// Bends the segment the given way, replacing any waypoints or curve.
+	pub(crate) fn with_direction(
+		self,
+		direction: InterpolationDirection,
+	) -> Self {
+		match self {
+			Self::Auto(_, bend) => Self::Auto(direction, bend),
+			Self::Waypoints(_) | Self::Curve(_) => {
+				Self::Auto(direction, Bend::Normal)
+			}
+		}
+	}
+
+	/// Draws the segment as a curve through its waypoints, or as straight
+	/// lines through them. Straightened curves without any waypoints bend
+	/// automatically.
+	pub(crate) fn with_curve(self, curve: bool) -> Self {
+		match (self, curve) {
+			(Self::Auto(..), true) => Self::Curve(Vec::new()),
+			(Self::Waypoints(points), true) => Self::Curve(points),
+			(Self::Curve(points), false) if points.is_empty() => {
+				Self::default()
+			}
+			(Self::Curve(points), false) => Self::Waypoints(points),
+			(unchanged, _) => unchanged,
+		}
+	}
+
+	/// Moves any waypoints along with the stations at either end.
+	fn moved(mut self, by: Vector) -> Self {
+		if let Some(points) = self.waypoints_mut() {
+			for p in points {
+				*p = *p + by;
+			}
+		}
+
+		self
+	}
+
+	/// Moves the bend towards the other end of the segment. Waypoints are
+	/// left where they are.
+	pub(crate) fn flipped(self) -> Self {
+		match self {
+			Self::Auto(direction, bend) => {
+				Self::Auto(direction, bend.flipped())
+			}
+			waypoints => waypoints,
+		}
+	}
+}
+
+/// How to draw part of a segment split from the rest, going from `start`
+/// through `points` to `end`. Segments that bend automatically still do if
+/// that gives the same path, and curves stay curves.
+fn split_interpolation(
+	original: &Interpolation,
+	start: Point,
+	points: Vec<Point>,
+	end: Point,
+) -> Interpolation {
+	let direction = match original {
+		Interpolation::Curve(_) => return Interpolation::Curve(points),
+		Interpolation::Waypoints(_) => return Interpolation::Waypoints(points),
+		Interpolation::Auto(direction, _) => *direction,
+	};
+
+	let wanted = Interpolation::Waypoints(points);
+	let same = |a: &[Point], b: &[Point]| {
+		a.len() == b.len()
+			&& a.iter().zip(b).all(|(&a, &b)| magnitude(a - b) < 1e-3)
+	};
+
+	[Bend::Normal, Bend::Flipped]
+		.into_iter()
+		.map(|bend| Interpolation::Auto(direction, bend))
+		.find(|auto| {
+			same(
+				&bundle::path(start, end, auto),
+				&bundle::path(start, end, &wanted),
+			)
+		})
+		.unwrap_or(wanted)
+}
+
+impl Default for Interpolation {
+	fn default() -> Self {
+		Self::Auto(InterpolationDirection::Auto, Bend::Normal)
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum InterpolationDirection {
 	Auto,
 	Horizontal,
 	Vertical,
 	Diagonal,
 }
 
+impl InterpolationDirection {
+	pub(crate) const ALL: [Self; 4] =
+		[Self::Auto, Self::Horizontal, Self::Vertical, Self::Diagonal];
+
+	/// The direction after this one in [`InterpolationDirection::ALL`],
+	/// going back to the start after the last.
+	pub(crate) fn next(self) -> Self {
+		let i = Self::ALL.iter().position(|&d| d == self).unwrap_or(0);
+		Self::ALL[(i + 1) % Self::ALL.len()]
+	}
+}
+
+impl fmt::Display for InterpolationDirection {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(match self {
+			Self::Auto => "Automatic",
+			Self::Horizontal => "Horizontal",
+			Self::Vertical => "Vertical",
+			Self::Diagonal => "Diagonal",
+		})
+	}
+}
+
+/// Which end of a segment its path is worked out from. Flipping it moves
+/// the bend towards the other end.
+#[derive(
+	Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize,
+)]
+pub(crate) enum Bend {
+	#[default]
+	Normal,
+	Flipped,
+}
+
+impl Bend {
+	fn flipped(self) -> Self {
+		match self {
+			Self::Normal => Self::Flipped,
+			Self::Flipped => Self::Normal,
+		}
+	}
+}
+
 impl Map {
-	pub(crate) fn view(
-		&self,
+	pub(crate) fn view<'a>(
+		&'a self,
+		selection: &'a Selection,
 		edit_mode: EditMode,
 		selected_line: LineIndex,
-	) -> Canvas<Message, impl Program<Message> + '_> {
-		Canvas::new(MapView::new(self, edit_mode, selected_line))
+		revision: Revision,
+		typing: bool,
+	) -> Element<'a, Message> {
+		MapView::new(
+			self,
+			selection,
+			edit_mode,
+			selected_line,
+			revision,
+			typing,
+		)
+		.into()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use iced::Point;
+
+	use super::{LineIndex, Map, StationIndex};
+
+	/// Builds a map for a test, from stations at the given points, in order,
+	/// and the lines run between them.
+	pub(super) struct TestMap(Map);
+
+	impl TestMap {
+		pub(super) fn new(stations: &[(f32, f32)]) -> Self {
+			let mut map = Map::default();
+			for &(x, y) in stations {
+				let _ = map.add_station(Point::new(x, y));
+			}
+			Self(map)
+		}
+
+		/// Adds segments of `line` between each station listed and the
+		/// next, by the order they were added in.
+		pub(super) fn line(
+			mut self,
+			line: LineIndex,
+			stations: &[usize],
+		) -> Self {
+			for pair in stations.windows(2) {
+				let _ = self.0.add_segment(
+					line,
+					StationIndex::new(pair[0]),
+					StationIndex::new(pair[1]),
+				);
+			}
+			self
+		}
+
+		pub(super) fn build(self) -> Map {
+			self.0
+		}
+	}
+
+	/// The stations each of a line's services calls at, by ID.
+	pub(super) fn services(map: &Map, line: LineIndex) -> Vec<Vec<u32>> {
+		map.services(line)
+			.iter()
+			.map(|service| service.stations().iter().map(|s| s.0).collect())
+			.collect()
 	}
 }
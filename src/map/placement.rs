@@ -0,0 +1,246 @@
+// Copyright (C) 2022  ThatsNoMoon
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of version 3 of the GNU Affero General Public License as published
+// by the Free Software Foundation.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU Affero General Public License
+// for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Automatic label placement. Each unpinned label is tried at every anchor,
+//! and the one overlapping the least is kept. Labels are placed greedily,
+//! busiest stations first, so the stations with the least room get first
+//! pick.
+
+use std::cmp::Reverse;
+
+use iced::{Point, Rectangle, Vector};
+use ordered_float::NotNan;
+
+use super::{
+	geometry::magnitude,
+	label::{Anchor, Label},
+	render::{pad, segment_shape, LINE_WIDTH, STATION_OUTER_SIZE},
+	Edit, Map, Station, StationIndex,
+};
+
+/// Cost of a label overlapping another label.
+const LABEL_COST: f32 = 100.0;
+/// Cost of a label covering a station.
+const STATION_COST: f32 = 80.0;
+/// Cost of a label covering a segment.
+const SEGMENT_COST: f32 = 40.0;
+/// Cost of a label pointing straight along the segments leaving its station.
+const DIRECTION_COST: f32 = 10.0;
+
+/// Space kept clear around labels.
+const MARGIN: f32 = 2.0;
+
+impl Map {
+	/// Moves every label that isn't pinned to where it overlaps the least,
+	/// returning the edit that undoes it.
+	pub(crate) fn place_labels(&mut self) -> Edit {
+		let segments: Vec<_> = self
+			.segment_indices()
+			.map(|index| {
+				let (start, end) = self
+					.graph
+					.edge_endpoints(index)
+					.expect("Edge index from iteration not in graph");
+				let shape = segment_shape(self, index, 0.0);
+
+				(start, end, shape.flatten())
+			})
+			.collect();
+
+		let transfers: Vec<_> = self
+			.transfer_ends()
+			.map(|(a, b)| (self.graph[a].position, self.graph[b].position))
+			.collect();
+
+		// Pinned labels stay where they are, so everything else avoids them
+		let mut placed: Vec<Rectangle> = self
+			.graph
+			.node_indices()
+			.filter(|&i| self.graph[i].label.pinned && self.labelled(i))
+			.map(|i| {
+				let station = &self.graph[i];
+				station.label.rect(station.position, &station.name)
+			})
+			.collect();
+
+		let mut to_place: Vec<StationIndex> = self
+			.graph
+			.node_indices()
+			.filter(|&i| !self.graph[i].label.pinned && self.labelled(i))
+			.collect();
+		to_place.sort_by_key(|&i| Reverse(self.segments_at(i).count()));
+
+		let mut undo = Vec::new();
+
+		for index in to_place {
+			let station = &self.graph[index];
+
+			// Which way each segment leaves the station, from the point
+			// closest to it that's outside of its marker
+			let leaving: Vec<Vector> = segments
+				.iter()
+				.flat_map(|(start, end, polylines)| {
+					let points = polylines.iter().flatten();
+					let away = if *start == index {
+						points.copied().find(|&p| {
+							magnitude(p - station.position) > STATION_OUTER_SIZE
+						})
+					} else if *end == index {
+						points.rev().copied().find(|&p| {
+							magnitude(p - station.position) > STATION_OUTER_SIZE
+						})
+					} else {
+						None
+					};
+
+					away.map(|p| {
+						let v = p - station.position;
+						v * (1.0 / magnitude(v))
+					})
+				})
+				.collect();
+
+			let cost = |anchor: Anchor| {
+				let rect = anchored(station, anchor);
+				let padded = pad(rect, MARGIN);
+
+				let labels = placed
+					.iter()
+					.filter(|other| padded.intersection(other).is_some())
+					.count() as f32;
+
+				let stations = self
+					.graph
+					.node_weights()
+					.filter(|other| {
+						circle_intersects(
+							padded,
+							other.position,
+							STATION_OUTER_SIZE,
+						)
+					})
+					.count() as f32;
+				let complexes = transfers
+					.iter()
+					.filter(|&&(a, b)| {
+						line_intersects(pad(padded, STATION_OUTER_SIZE), a, b)
+					})
+					.count() as f32;
+
+				let lines = pad(padded, LINE_WIDTH / 2.0);
+				let segments = segments
+					.iter()
+					.filter(|(.., polylines)| {
+						polylines.iter().any(|points| {
+							points
+								.windows(2)
+								.any(|w| line_intersects(lines, w[0], w[1]))
+						})
+					})
+					.count() as f32;
+
+				let direction = anchor.direction();
+				let along = leaving
+					.iter()
+					.map(|v| (v.x * direction.x + v.y * direction.y).max(0.0))
+					.sum::<f32>();
+
+				labels * LABEL_COST
+					+ (stations + complexes) * STATION_COST
+					+ segments * SEGMENT_COST
+					+ along * DIRECTION_COST
+					+ preference(anchor)
+			};
+
+			let anchor = Anchor::ALL
+				.into_iter()
+				.min_by_key(|&anchor| NotNan::new(cost(anchor)).unwrap())
+				.expect("No anchors to choose from");
+
+			placed.push(anchored(station, anchor));
+
+			let mut station = station.clone();
+			station.label = Label {
+				anchor,
+				..Label::default()
+			};
+
+			if station.label != self.graph[index].label {
+				undo.push(self.replace_station(station));
+			}
+		}
+
+		undo.reverse();
+		Edit::Group(undo)
+	}
+}
+
+fn anchored(station: &Station, anchor: Anchor) -> Rectangle {
+	Label {
+		anchor,
+		..Label::default()
+	}
+	.rect(station.position, &station.name)
+}
+
+/// Small costs that break ties between otherwise equal anchors, favouring
+/// labels beside their stations over those above, below, or diagonal.
+fn preference(anchor: Anchor) -> f32 {
+	match anchor {
+		Anchor::E => 0.0,
+		Anchor::W => 0.1,
+		Anchor::N | Anchor::S => 0.2,
+		_ => 0.3,
+	}
+}
+
+fn circle_intersects(rect: Rectangle, center: Point, radius: f32) -> bool {
+	let closest = Point::new(
+		center.x.clamp(rect.x, rect.x + rect.width),
+		center.y.clamp(rect.y, rect.y + rect.height),
+	);
+
+	magnitude(closest - center) < radius
+}
+
+/// Whether the line from `a` to `b` passes through the rectangle, found by
+/// clipping the line to it.
+fn line_intersects(rect: Rectangle, a: Point, b: Point) -> bool {
+	let d = b - a;
+
+	let mut t_min = 0.0_f32;
+	let mut t_max = 1.0_f32;
+
+	for (p, q) in [
+		(-d.x, a.x - rect.x),
+		(d.x, rect.x + rect.width - a.x),
+		(-d.y, a.y - rect.y),
+		(d.y, rect.y + rect.height - a.y),
+	] {
+		if p == 0.0 {
+			if q < 0.0 {
+				return false;
+			}
+		} else {
+			let t = q / p;
+			if p < 0.0 {
+				t_min = t_min.max(t);
+			} else {
+				t_max = t_max.min(t);
+			}
+		}
+	}
+
+	t_min <= t_max
+}
@@ -0,0 +1,562 @@
+// Copyright (C) 2022  ThatsNoMoon
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of version 3 of the GNU Affero General Public License as published
+// by the Free Software Foundation.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU Affero General Public License
+// for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::{cell::OnceCell, collections::HashMap, fmt, io, sync::Arc};
+
+use iced::{Color, Point, Vector};
+use ron::ser::PrettyConfig;
+use serde::{Deserialize, Serialize};
+
+use super::{
+	render::snap, Anchor, Edit, Graph, Interpolation, Label, Line, LineIndex,
+	Link, Map, Marker, Selection, Service, SpatialIndex, Station, StationId,
+	Style,
+};
+use crate::color::ColorExt;
+
+/// File extension used for project files.
+pub(crate) const EXTENSION: &str = "metro";
+
+/// Current version of the project format. Bump this whenever a change is made
+/// that older versions can't read. Older files still load, since everything
+/// added since has a default.
+///
+/// Version 2 added segments with waypoints and curves, turn radii, services,
+/// station markers and transfers.
+const VERSION: u32 = 2;
+
+#[derive(Debug, Clone)]
+pub(crate) enum Error {
+	Io(Arc<io::Error>),
+	Syntax(Arc<ron::Error>),
+	UnsupportedVersion(u32),
+	UnknownStation(StationId),
+	DuplicateStation(StationId),
+	UnknownLine(LineIndex),
+	InvalidColor(String),
+}
+
+impl fmt::Display for Error {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Error::Io(e) => write!(f, "{e}"),
+			Error::Syntax(e) => write!(f, "invalid project file: {e}"),
+			Error::UnsupportedVersion(v) => write!(
+				f,
+				"project version {v} is not supported (expected at most \
+				 {VERSION})"
+			),
+			Error::UnknownStation(StationId(id)) => {
+				write!(f, "segment refers to unknown station {id}")
+			}
+			Error::DuplicateStation(StationId(id)) => {
+				write!(f, "station {id} appears more than once")
+			}
+			Error::UnknownLine(line) => {
+				write!(f, "segment refers to unknown line {line}")
+			}
+			Error::InvalidColor(c) => write!(f, "invalid line color {c:?}"),
+		}
+	}
+}
+
+impl From<io::Error> for Error {
+	fn from(e: io::Error) -> Self {
+		Self::Io(Arc::new(e))
+	}
+}
+
+impl From<ron::Error> for Error {
+	fn from(e: ron::Error) -> Self {
+		Self::Syntax(Arc::new(e))
+	}
+}
+
+/// Lists of points, which are saved as pairs of coordinates.
+pub(super) mod points {
+	use iced::Point;
+	use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+	pub(in crate::map) fn serialize<S: Serializer>(
+		points: &[Point],
+		serializer: S,
+	) -> Result<S::Ok, S::Error> {
+		let pairs: Vec<_> = points.iter().map(|p| (p.x, p.y)).collect();
+		pairs.serialize(serializer)
+	}
+
+	pub(in crate::map) fn deserialize<'de, D: Deserializer<'de>>(
+		deserializer: D,
+	) -> Result<Vec<Point>, D::Error> {
+		let pairs = Vec::<(f32, f32)>::deserialize(deserializer)?;
+		Ok(pairs.into_iter().map(|(x, y)| Point::new(x, y)).collect())
+	}
+}
+
+/// Only the version is parsed first, so files from newer versions are
+/// reported as such instead of as syntax errors.
+#[derive(Deserialize)]
+struct Header {
+	version: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Project {
+	version: u32,
+	lines: Vec<LineData>,
+	#[serde(default)]
+	style: Style,
+	stations: Vec<StationData>,
+	segments: Vec<SegmentData>,
+	/// The stations at either end of each transfer.
+	#[serde(default)]
+	transfers: Vec<(StationId, StationId)>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct LineData {
+	#[serde(default)]
+	name: String,
+	#[serde(default)]
+	code: String,
+	/// Hex color, e.g. `#33bbff`.
+	color: String,
+	/// The stations each of the line's services calls at, in order.
+	#[serde(default)]
+	services: Vec<Vec<StationId>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StationData {
+	id: StationId,
+	position: (f32, f32),
+	#[serde(default)]
+	name: String,
+	#[serde(default)]
+	label: LabelData,
+	#[serde(default)]
+	marker: Marker,
+	#[serde(default)]
+	accessible: bool,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct LabelData {
+	anchor: Anchor,
+	offset: (f32, f32),
+	#[serde(default)]
+	pinned: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SegmentData {
+	start: StationId,
+	end: StationId,
+	line: LineIndex,
+	interpolation: Interpolation,
+	#[serde(default)]
+	turn_radius: Option<f32>,
+}
+
+impl Map {
+	pub(crate) fn serialize(&self) -> Result<String, Error> {
+		let graph = &self.graph;
+
+		let project = Project {
+			version: VERSION,
+			lines: self
+				.lines
+				.iter()
+				.map(|line| LineData {
+					name: line.name.clone(),
+					code: line.code.clone(),
+					color: format!("#{:06x}", line.color.to_rgb32()),
+					services: line
+						.services
+						.iter()
+						.map(|service| service.stations().to_vec())
+						.collect(),
+				})
+				.collect(),
+			style: self.style,
+			stations: graph
+				.node_weights()
+				.map(|station| StationData {
+					id: station.id,
+					position: (station.position.x, station.position.y),
+					name: station.name.clone(),
+					label: LabelData {
+						anchor: station.label.anchor,
+						offset: (
+							station.label.offset.x,
+							station.label.offset.y,
+						),
+						pinned: station.label.pinned,
+					},
+					marker: station.marker,
+					accessible: station.accessible,
+				})
+				.collect(),
+			segments: self
+				.segment_indices()
+				.map(|index| {
+					let (start, end) = graph
+						.edge_endpoints(index)
+						.expect("Edge index from iteration not in graph");
+					let segment = self.segment(index);
+
+					SegmentData {
+						start: graph[start].id,
+						end: graph[end].id,
+						line: segment.line,
+						interpolation: segment.interpolation.clone(),
+						turn_radius: segment.turn_radius,
+					}
+				})
+				.collect(),
+			transfers: self
+				.transfer_ends()
+				.map(|(a, b)| (graph[a].id, graph[b].id))
+				.collect(),
+		};
+
+		Ok(ron::ser::to_string_pretty(&project, PrettyConfig::new())?)
+	}
+
+	pub(crate) fn deserialize(source: &str) -> Result<Self, Error> {
+		let Header { version } = ron::from_str(source)?;
+		if version > VERSION {
+			return Err(Error::UnsupportedVersion(version));
+		}
+
+		let project: Project = ron::from_str(source)?;
+
+		let lines = project
+			.lines
+			.into_iter()
+			.map(|line| {
+				let color = Color::from_hex(&line.color)
+					.ok_or(Error::InvalidColor(line.color))?;
+
+				Ok(Line {
+					name: line.name,
+					code: line.code,
+					color,
+					services: line
+						.services
+						.into_iter()
+						.map(Service::new)
+						.collect(),
+				})
+			})
+			.collect::<Result<Vec<_>, Error>>()?;
+
+		let mut map = Self {
+			graph: Graph::with_capacity(
+				project.stations.len(),
+				project.segments.len(),
+			),
+			lines,
+			style: project.style,
+			spatial: SpatialIndex::default(),
+			bundles: OnceCell::new(),
+			next_station_id: 0,
+			next_segment_id: 0,
+		};
+		let mut indices = HashMap::with_capacity(project.stations.len());
+
+		for StationData {
+			id,
+			position,
+			name,
+			label,
+			marker,
+			accessible,
+		} in project.stations
+		{
+			let index = map.graph.add_node(Station {
+				id,
+				position: Point::new(position.0, position.1),
+				name,
+				label: Label {
+					anchor: label.anchor,
+					offset: Vector::new(label.offset.0, label.offset.1),
+					pinned: label.pinned,
+				},
+				marker,
+				accessible,
+			});
+			if indices.insert(id, index).is_some() {
+				return Err(Error::DuplicateStation(id));
+			}
+			map.next_station_id = map.next_station_id.max(id.0 + 1);
+		}
+
+		for segment in project.segments {
+			let station =
+				|id| indices.get(&id).copied().ok_or(Error::UnknownStation(id));
+
+			if segment.line as usize >= map.lines.len() {
+				return Err(Error::UnknownLine(segment.line));
+			}
+
+			let (start, end) = (station(segment.start)?, station(segment.end)?);
+			let turn_radius = segment.turn_radius;
+			let mut segment =
+				map.new_segment(segment.line, segment.interpolation);
+			segment.turn_radius = turn_radius;
+			map.graph.add_edge(start, end, Link::Segment(segment));
+		}
+
+		for (a, b) in project.transfers {
+			let station =
+				|id| indices.get(&id).copied().ok_or(Error::UnknownStation(id));
+
+			let (a, b) = (station(a)?, station(b)?);
+			if a != b && map.transfer_between(a, b).is_none() {
+				map.graph.add_edge(a, b, Link::Transfer);
+			}
+		}
+
+		map.rebuild_spatial();
+
+		// Older projects don't have services, so they're worked out from the
+		// segments, as they are if they don't fit the segments
+		if !map.services_match() {
+			map.rebuild_services();
+		}
+
+		Ok(map)
+	}
+
+	/// Serializes the selected stations and the segments between them, in
+	/// the same form as a project, to be added to a map with [`Map::paste`].
+	pub(crate) fn copy(&self, selection: &Selection) -> Result<String, Error> {
+		let mut fragment = self.clone();
+		fragment
+			.graph
+			.retain_nodes(|graph, i| selection.contains_station(graph[i].id));
+		fragment.rebuild_spatial();
+		fragment.rebuild_services();
+
+		fragment.serialize()
+	}
+
+	/// Adds the stations and segments copied with [`Map::copy`], centered
+	/// on the grid point closest to `at`. Segments are put on whichever line
+	/// is most like the one they were on, with lines added where none are.
+	///
+	/// Returns the edit that undoes this, and what was added.
+	pub(crate) fn paste(
+		&mut self,
+		source: &str,
+		at: Point,
+	) -> Result<(Edit, Selection), Error> {
+		let fragment = Self::deserialize(source)?;
+		let graph = &fragment.graph;
+
+		let mut undo = Vec::new();
+		let mut pasted = Selection::default();
+
+		let (mut min, mut max) = (
+			Point::new(f32::INFINITY, f32::INFINITY),
+			Point::new(f32::NEG_INFINITY, f32::NEG_INFINITY),
+		);
+		for station in graph.node_weights() {
+			min.x = min.x.min(station.position.x);
+			min.y = min.y.min(station.position.y);
+			max.x = max.x.max(station.position.x);
+			max.y = max.y.max(station.position.y);
+		}
+		let center = Point::new((min.x + max.x) / 2.0, (min.y + max.y) / 2.0);
+		let by = snap(at) - snap(center);
+
+		let mut ids = HashMap::with_capacity(graph.node_count());
+		for index in graph.node_indices() {
+			let id = StationId(self.next_station_id);
+			self.next_station_id += 1;
+
+			let station = &graph[index];
+			undo.push(self.insert_station(Station {
+				id,
+				position: station.position + by,
+				..station.clone()
+			}));
+			ids.insert(index, id);
+			pasted.insert_station(id);
+		}
+
+		for (a, b) in fragment.transfer_ends() {
+			undo.push(self.insert_transfer(ids[&a], ids[&b]));
+		}
+
+		let mut lines = HashMap::new();
+		for index in fragment.segment_indices() {
+			let (start, end) = graph
+				.edge_endpoints(index)
+				.expect("Edge index from iteration not in graph");
+			let segment = fragment.segment(index);
+
+			let line = match lines.get(&segment.line) {
+				Some(&line) => line,
+				None => {
+					let copied = &fragment.lines[segment.line as usize];
+					let line = match self.matching_line(copied) {
+						Some(line) => line,
+						None => {
+							let line = self.lines.len() as LineIndex;
+							let copied = Line {
+								services: Vec::new(),
+								..copied.clone()
+							};
+							undo.push(self.insert_line(line, copied));
+							line
+						}
+					};
+					lines.insert(segment.line, line);
+					line
+				}
+			};
+
+			let interpolation = segment.interpolation.clone().moved(by);
+			let turn_radius = segment.turn_radius;
+			let mut segment = self.new_segment(line, interpolation);
+			segment.turn_radius = turn_radius;
+			pasted.insert_segment(segment.id);
+			undo.push(self.insert_segment(ids[&start], ids[&end], segment));
+		}
+
+		undo.reverse();
+		Ok((Edit::Group(undo), pasted))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::map::{tests::TestMap, StationIndex};
+
+	fn station(i: usize) -> StationIndex {
+		StationIndex::new(i)
+	}
+
+	/// Two lines meeting at a station, one with a waypoint, a named station
+	/// with a pinned label, and two stations joined by a transfer.
+	fn sample() -> Map {
+		let stations = [
+			(0.0, 0.0),
+			(100.0, 0.0),
+			(200.0, 100.0),
+			(100.0, 200.0),
+			(140.0, 200.0),
+		];
+		let mut map = TestMap::new(&stations)
+			.line(0, &[0, 1, 2])
+			.line(1, &[1, 3])
+			.build();
+
+		let bent = map.line_segment(0, station(1), station(2)).unwrap();
+		let _ =
+			map.insert_waypoint(map.segment(bent).id, Point::new(150.0, 20.0));
+		let _ = map.rename_station(StationId(0), "Alpha".to_owned());
+		let _ = map.set_label_anchor(StationId(0), Anchor::NW);
+
+		let mut selection = Selection::default();
+		selection.insert_station(StationId(3));
+		selection.insert_station(StationId(4));
+		let _ = map.join_stations(&selection);
+
+		map
+	}
+
+	#[test]
+	fn round_trip() {
+		let map = sample();
+		let source = map.serialize().unwrap();
+		let loaded = Map::deserialize(&source).unwrap();
+
+		assert_eq!(loaded.serialize().unwrap(), source);
+
+		assert_eq!(loaded.graph.node_count(), 5);
+		let alpha = loaded.station(station(0));
+		assert_eq!(alpha.name(), "Alpha");
+		assert_eq!(alpha.label().anchor(), Anchor::NW);
+		assert!(alpha.label().pinned());
+
+		let bent = loaded.line_segment(0, station(1), station(2)).unwrap();
+		assert_eq!(
+			loaded.waypoints(loaded.segment(bent).id),
+			[Point::new(150.0, 20.0)],
+		);
+
+		for line in 0..2 {
+			assert_eq!(loaded.services(line), map.services(line));
+		}
+		assert!(loaded.same_complex(station(3), station(4)));
+		assert!(!loaded.same_complex(station(1), station(3)));
+	}
+
+	#[test]
+	fn versions() {
+		let source = sample().serialize().unwrap();
+		assert!(source.contains(&format!("version: {VERSION},")));
+
+		let old = "(version: 1, lines: [(color: \"#33bbff\")], \
+		           stations: [(id: 0, position: (0, 0)), \
+		           (id: 1, position: (100, 0))], \
+		           segments: [(start: 0, end: 1, line: 0, \
+		           interpolation: Auto(Auto)), ])";
+		let map = Map::deserialize(old).unwrap();
+		assert_eq!(map.segment_indices().count(), 1);
+		assert_eq!(map.services(0).len(), 1);
+
+		let newer = source.replace(
+			&format!("version: {VERSION},"),
+			&format!("version: {},", VERSION + 1),
+		);
+		assert!(matches!(
+			Map::deserialize(&newer),
+			Err(Error::UnsupportedVersion(v)) if v == VERSION + 1,
+		));
+	}
+
+	#[test]
+	fn short_services() {
+		let source = format!(
+			"(version: {VERSION}, \
+			 lines: [(color: \"#33bbff\", services: [[], [0]])], \
+			 stations: [(id: 0, position: (0, 0)), \
+			 (id: 1, position: (100, 0))], segments: [])",
+		);
+
+		// Services calling at fewer than two stations are dropped
+		let mut map = Map::deserialize(&source).unwrap();
+		assert!(map.services(0).is_empty());
+
+		let _ = map.add_segment(0, station(0), station(1));
+		assert_eq!(map.services(0).len(), 1);
+		assert!(map.services_match());
+	}
+
+	#[test]
+	fn duplicate_station() {
+		let map = TestMap::new(&[(0.0, 0.0), (100.0, 0.0)]).build();
+		let source = map.serialize().unwrap().replace("id: 1,", "id: 0,");
+
+		assert!(matches!(
+			Map::deserialize(&source),
+			Err(Error::DuplicateStation(StationId(0))),
+		));
+	}
+}
@@ -0,0 +1,506 @@
+// Copyright (C) 2022  ThatsNoMoon
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of version 3 of the GNU Affero General Public License as published
+// by the Free Software Foundation.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU Affero General Public License
+// for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use iced::{Color, Point, Rectangle, Size, Vector};
+use petgraph::graph::EdgeIndex;
+
+use super::{
+	bundle::path,
+	font,
+	geometry::{curved_path, normal, rounded_path, Shape},
+	Index, Interpolation, Map, Marker, StationIndex,
+};
+use crate::color::ColorExt;
+
+pub(super) const STATION_INNER_SIZE: f32 = 15.0;
+pub(super) const STATION_OUTER_SIZE: f32 = 20.0;
+/// How far ticks and terminus bars reach past the side of their line.
+pub(super) const TICK_LENGTH: f32 = 10.0;
+pub(super) const TICK_WIDTH: f32 = 6.0;
+pub(super) const REQUEST_SIZE: f32 = 8.0;
+pub(super) const BADGE_SIZE: f32 = 8.0;
+
+pub(super) const GRID_SIZE: f32 = 50.0;
+
+/// The turn radius of new maps.
+pub(super) const TURN_RADIUS: f32 = 40.0;
+
+pub(super) const LINE_WIDTH: f32 = 10.0;
+
+pub(super) const LABEL_SIZE: f32 = 20.0;
+/// Space between a station and its label.
+pub(super) const LABEL_GAP: f32 = 4.0;
+
+/// Width of the outline around selected stations and segments.
+pub(super) const SELECTION_WIDTH: f32 = 4.0;
+/// Radius of the handles on the waypoints of selected segments.
+pub(super) const WAYPOINT_SIZE: f32 = 7.0;
+
+pub(super) const BACKGROUND_COLOR: u32 = 0x19191D;
+pub(super) const GRID_COLOR: u32 = 0x3d3d4a;
+pub(super) const STATION_OUTER_COLOR: u32 = 0xd8e0ef;
+pub(super) const STATION_INNER_COLOR: u32 = 0x030405;
+pub(super) const LABEL_COLOR: u32 = 0xd8e0ef;
+pub(super) const SELECTION_COLOR: u32 = 0xffc94d;
+pub(super) const BADGE_COLOR: u32 = 0x1d6fd8;
+
+/// Something the map can be drawn onto, such as the editor canvas or one of
+/// the exporters.
+pub(super) trait Surface {
+	fn fill(&mut self, shape: &Shape, color: Color);
+
+	fn stroke(&mut self, shape: &Shape, pen: Pen);
+
+	/// Draws a line of text in the label font, `size` units tall.
+	fn text(
+		&mut self,
+		content: &str,
+		top_left: Point,
+		size: f32,
+		color: Color,
+	) {
+		self.fill(&font::outline(content, top_left, size), color);
+	}
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Pen {
+	pub(super) color: Color,
+	pub(super) width: f32,
+	pub(super) cap: Cap,
+}
+
+/// How the ends and corners of stroked shapes are drawn. Round caps are
+/// always paired with round joins, and butt caps with miter joins.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Cap {
+	Butt,
+	Round,
+}
+
+impl Pen {
+	pub(super) fn grid() -> Self {
+		Self {
+			color: Color::from_rgb32(GRID_COLOR),
+			width: 2.0,
+			cap: Cap::Butt,
+		}
+	}
+
+	pub(super) fn line(color: Color) -> Self {
+		Self {
+			color,
+			width: LINE_WIDTH,
+			cap: Cap::Round,
+		}
+	}
+
+	/// The pen ticks and terminus bars are drawn with.
+	pub(super) fn tick(color: Color) -> Self {
+		Self {
+			color,
+			width: TICK_WIDTH,
+			cap: Cap::Butt,
+		}
+	}
+}
+
+/// The path of a segment on the map, moved `offset` to the right of the line
+/// between its stations.
+pub(super) fn segment_shape(
+	map: &Map,
+	index: EdgeIndex<Index>,
+	offset: f32,
+) -> Shape {
+	let graph = &map.graph;
+	let (start, end) = graph
+		.edge_endpoints(index)
+		.expect("Segment index not in graph");
+	let segment = map.segment(index);
+	let interpolation = &segment.interpolation;
+
+	Shape::new(|b| {
+		let path =
+			path(graph[start].position, graph[end].position, interpolation);
+		if interpolation.is_curve() {
+			curved_path(&path, map.curve_ends(index), offset, b);
+		} else {
+			let radius = segment.turn_radius(&map.style);
+			rounded_path(&path, radius, offset, b);
+		}
+	})
+}
+
+/// Draws a segment that isn't on the map, such as one being added. Curves
+/// aren't lined up with anything around them.
+pub(super) fn draw_segment(
+	start: Point,
+	end: Point,
+	interpolation: &Interpolation,
+	radius: f32,
+	color: Color,
+	surface: &mut impl Surface,
+) {
+	let shape = Shape::new(|b| {
+		let path = path(start, end, interpolation);
+		if interpolation.is_curve() {
+			let ends = (path[1] - start, end - path[path.len() - 2]);
+			curved_path(&path, ends, 0.0, b);
+		} else {
+			rounded_path(&path, radius, 0.0, b);
+		}
+	});
+
+	surface.stroke(&shape, Pen::line(color));
+}
+
+/// The path of every segment on the map as it's drawn, with segments sharing
+/// a path side by side. If an area is given, segments that can't be within it
+/// are left out.
+pub(super) fn segment_shapes(
+	map: &Map,
+	area: Option<Rectangle>,
+) -> Vec<(EdgeIndex<Index>, Shape)> {
+	let bundles = map.bundles();
+
+	// Segments are kept in the same order however they were found, so they
+	// overlap the same way wherever they're drawn
+	let segments: Vec<_> = match area {
+		Some(area) => {
+			let mut nearby: Vec<_> = map
+				.segments_in(area)
+				.into_iter()
+				.map(|index| (bundles.of(index), index))
+				.collect();
+			nearby.sort_unstable_by_key(|&(order, _)| order);
+			nearby
+		}
+		None => bundles
+			.iter()
+			.enumerate()
+			.flat_map(|(i, bundle)| {
+				bundle
+					.segments
+					.iter()
+					.enumerate()
+					.map(move |(position, &index)| ((i, position), index))
+			})
+			.collect(),
+	};
+
+	segments
+		.into_iter()
+		.map(|((bundle, position), index)| {
+			let (start, _) = map
+				.graph
+				.edge_endpoints(index)
+				.expect("Edge index from bundle not in graph");
+			let offset = bundles.get(bundle).offset(position, start);
+
+			(index, segment_shape(map, index, offset))
+		})
+		.collect()
+}
+
+/// Draws every segment on the map, or those within an area, with segments
+/// sharing a path drawn side by side.
+pub(super) fn draw_segments(
+	map: &Map,
+	area: Option<Rectangle>,
+	surface: &mut impl Surface,
+) {
+	for (index, shape) in segment_shapes(map, area) {
+		let line = map.segment(index).line;
+		surface.stroke(&shape, Pen::line(map.lines[line as usize].color));
+	}
+}
+
+/// The ends of the line through the middle of a station's marker, which is
+/// stretched across the widest bundle of segments the station is on.
+pub(super) fn station_ends(map: &Map, index: StationIndex) -> (Point, Point) {
+	let position = map.graph[index].position;
+	let (across, half_width) = station_across(map, index);
+
+	(
+		position - across * half_width,
+		position + across * half_width,
+	)
+}
+
+/// The unit vector across the widest bundle of segments a station is on, and
+/// how far its outermost segments are from the station. Stations without any
+/// segments are crossed from top to bottom.
+fn station_across(map: &Map, index: StationIndex) -> (Vector, f32) {
+	let widest = map.bundles().widest_at(map, index);
+
+	let across = widest
+		.map(|bundle| normal(bundle.direction_from(map, index)))
+		.filter(|&across| across != Vector::new(0.0, 0.0));
+
+	match (widest, across) {
+		(Some(bundle), Some(across)) => (across, bundle.half_width()),
+		_ => (Vector::new(0.0, 1.0), 0.0),
+	}
+}
+
+/// Draws every station on the map, which should be done after the segments so
+/// the stations are on top. Stations in a complex are joined up into one
+/// marker along their transfers. If an area is given, only stations within
+/// it are drawn.
+pub(super) fn draw_stations(
+	map: &Map,
+	area: Option<Rectangle>,
+	surface: &mut impl Surface,
+) {
+	// Markers, and their ticks and badges, reach a little way past their
+	// stations
+	let stations = match area {
+		Some(area) => map.stations_in(pad(area, STATION_OUTER_SIZE * 4.0)),
+		None => map.graph.node_indices().collect(),
+	};
+
+	let outer = Color::from_rgb32(STATION_OUTER_COLOR);
+
+	let transfers: Vec<_> = match area {
+		Some(area) => map
+			.transfers_in(area)
+			.into_iter()
+			.map(|index| {
+				map.graph
+					.edge_endpoints(index)
+					.expect("Transfer index not in graph")
+			})
+			.collect(),
+		None => map.transfer_ends().collect(),
+	};
+
+	for (a, b) in transfers {
+		let (a, b) = (map.graph[a].position, map.graph[b].position);
+		surface.fill(&Shape::capsule(a, b, STATION_OUTER_SIZE), outer);
+		surface.fill(
+			&Shape::capsule(a, b, STATION_INNER_SIZE),
+			Color::from_rgb32(STATION_INNER_COLOR),
+		);
+	}
+
+	for index in stations {
+		let (a, b) = station_ends(map, index);
+		let position = map.graph[index].position;
+		let line = map
+			.station_line(index)
+			.map(|line| map.lines[line as usize].color);
+
+		// Ticks and bars stick out past the outermost line
+		let (across, half_width) = station_across(map, index);
+		let reach = half_width + LINE_WIDTH / 2.0 + TICK_LENGTH;
+
+		match (map.station_marker(index), line) {
+			(Marker::Tick, Some(color)) => {
+				surface.stroke(
+					&Shape::line(position, position + across * reach),
+					Pen::tick(color),
+				);
+			}
+			(Marker::Terminus, Some(color)) => {
+				surface.stroke(
+					&Shape::line(
+						position - across * reach,
+						position + across * reach,
+					),
+					Pen::tick(color),
+				);
+			}
+			(Marker::Request, Some(color)) => {
+				let shape = Shape::capsule(a, b, REQUEST_SIZE);
+				surface.fill(&shape, outer);
+				surface.stroke(&shape, Pen::tick(color));
+			}
+			_ => {
+				surface.fill(&Shape::capsule(a, b, STATION_OUTER_SIZE), outer);
+				surface.fill(
+					&Shape::capsule(a, b, STATION_INNER_SIZE),
+					Color::from_rgb32(STATION_INNER_COLOR),
+				);
+			}
+		}
+
+		if map.graph[index].accessible {
+			// Above the end of the marker, on the opposite side to ticks
+			let center = b - across * (STATION_OUTER_SIZE + BADGE_SIZE);
+			surface.fill(&Shape::circle(center, BADGE_SIZE), outer);
+			surface.fill(
+				&Shape::circle(center, BADGE_SIZE - 2.0),
+				Color::from_rgb32(BADGE_COLOR),
+			);
+		}
+	}
+}
+
+/// Draws the name of every station, and once for each complex, which should
+/// be done last so labels aren't hidden by anything. If an area is given,
+/// only names within it are drawn.
+pub(super) fn draw_labels(
+	map: &Map,
+	area: Option<Rectangle>,
+	surface: &mut impl Surface,
+) {
+	let stations = match area {
+		Some(area) => {
+			// Drawn in the same order as without an area, so overlapping
+			// labels look the same
+			let mut stations = map.labels_in(area);
+			stations.sort_unstable();
+			stations
+		}
+		None => map.graph.node_indices().collect(),
+	};
+
+	for index in stations {
+		if !map.labelled(index) {
+			continue;
+		}
+
+		let station = &map.graph[index];
+		let rect = station.label.rect(station.position, &station.name);
+		if area.is_some_and(|area| area.intersection(&rect).is_none()) {
+			continue;
+		}
+
+		surface.text(
+			&station.name,
+			rect.position(),
+			LABEL_SIZE,
+			Color::from_rgb32(LABEL_COLOR),
+		);
+	}
+}
+
+/// A rectangle grown by `padding` on every side.
+pub(super) fn pad(rect: Rectangle, padding: f32) -> Rectangle {
+	Rectangle {
+		x: rect.x - padding,
+		y: rect.y - padding,
+		width: rect.width + padding * 2.0,
+		height: rect.height + padding * 2.0,
+	}
+}
+
+/// The grid point closest to `p`.
+pub(super) fn snap(p: Point) -> Point {
+	Point::new(
+		(p.x / GRID_SIZE).round() * GRID_SIZE,
+		(p.y / GRID_SIZE).round() * GRID_SIZE,
+	)
+}
+
+/// Draws grid lines over the given area, aligned to [`GRID_SIZE`].
+pub(super) fn draw_grid(
+	top_left: Point,
+	bottom_right: Point,
+	surface: &mut impl Surface,
+) {
+	let pen = Pen::grid();
+
+	let start_x = (top_left.x / GRID_SIZE).ceil() * GRID_SIZE;
+	let mut x = start_x;
+	while x <= bottom_right.x {
+		let top = Point::new(x, top_left.y);
+		let bottom = Point::new(x, bottom_right.y);
+		surface.stroke(&Shape::line(top, bottom), pen);
+		x += GRID_SIZE;
+	}
+
+	let start_y = (top_left.y / GRID_SIZE).ceil() * GRID_SIZE;
+	let mut y = start_y;
+	while y <= bottom_right.y {
+		let left = Point::new(top_left.x, y);
+		let right = Point::new(bottom_right.x, y);
+		surface.stroke(&Shape::line(left, right), pen);
+		y += GRID_SIZE;
+	}
+}
+
+/// A [`Surface`] that only records how much space is drawn on.
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Bounds {
+	pub(super) min: Point,
+	pub(super) max: Point,
+}
+
+impl Default for Bounds {
+	fn default() -> Self {
+		Self {
+			min: Point::new(f32::INFINITY, f32::INFINITY),
+			max: Point::new(f32::NEG_INFINITY, f32::NEG_INFINITY),
+		}
+	}
+}
+
+impl Bounds {
+	fn extend(&mut self, shape: &Shape, padding: f32) {
+		for (p, extent) in shape.extents() {
+			let extent = extent + padding;
+			self.include(p - Vector::new(extent, extent));
+			self.include(p + Vector::new(extent, extent));
+		}
+	}
+
+	fn include(&mut self, p: Point) {
+		self.min.x = self.min.x.min(p.x);
+		self.min.y = self.min.y.min(p.y);
+		self.max.x = self.max.x.max(p.x);
+		self.max.y = self.max.y.max(p.y);
+	}
+
+	pub(super) fn is_empty(&self) -> bool {
+		self.min.x > self.max.x || self.min.y > self.max.y
+	}
+
+	pub(super) fn padded(self, padding: f32) -> Self {
+		Self {
+			min: Point::new(self.min.x - padding, self.min.y - padding),
+			max: Point::new(self.max.x + padding, self.max.y + padding),
+		}
+	}
+
+	pub(super) fn width(&self) -> f32 {
+		self.max.x - self.min.x
+	}
+
+	pub(super) fn height(&self) -> f32 {
+		self.max.y - self.min.y
+	}
+}
+
+impl Surface for Bounds {
+	fn fill(&mut self, shape: &Shape, _color: Color) {
+		self.extend(shape, 0.0);
+	}
+
+	fn stroke(&mut self, shape: &Shape, pen: Pen) {
+		self.extend(shape, pen.width / 2.0);
+	}
+
+	fn text(
+		&mut self,
+		content: &str,
+		top_left: Point,
+		size: f32,
+		_color: Color,
+	) {
+		let Size { width, height } = font::measure(content, size);
+		self.include(top_left);
+		self.include(top_left + Vector::new(width, height));
+	}
+}
@@ -0,0 +1,313 @@
+// Copyright (C) 2022  ThatsNoMoon
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of version 3 of the GNU Affero General Public License as published
+// by the Free Software Foundation.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU Affero General Public License
+// for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::collections::BTreeSet;
+
+use iced::Vector;
+
+use super::{Edit, Interpolation, LineIndex, Map, SegmentId, StationId};
+
+/// Stations and segments picked out to be edited together.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct Selection {
+	stations: BTreeSet<StationId>,
+	segments: BTreeSet<SegmentId>,
+}
+
+impl Selection {
+	pub(super) fn station(id: StationId) -> Self {
+		Self {
+			stations: BTreeSet::from([id]),
+			..Self::default()
+		}
+	}
+
+	pub(super) fn segment(id: SegmentId) -> Self {
+		Self {
+			segments: BTreeSet::from([id]),
+			..Self::default()
+		}
+	}
+
+	pub(crate) fn is_empty(&self) -> bool {
+		self.stations.is_empty() && self.segments.is_empty()
+	}
+
+	pub(crate) fn has_segments(&self) -> bool {
+		!self.segments.is_empty()
+	}
+
+	pub(crate) fn station_count(&self) -> usize {
+		self.stations.len()
+	}
+
+	pub(super) fn stations(&self) -> impl Iterator<Item = StationId> + '_ {
+		self.stations.iter().copied()
+	}
+
+	pub(super) fn segments(&self) -> impl Iterator<Item = SegmentId> + '_ {
+		self.segments.iter().copied()
+	}
+
+	pub(super) fn contains_station(&self, id: StationId) -> bool {
+		self.stations.contains(&id)
+	}
+
+	pub(super) fn contains_segment(&self, id: SegmentId) -> bool {
+		self.segments.contains(&id)
+	}
+
+	pub(super) fn insert_station(&mut self, id: StationId) {
+		self.stations.insert(id);
+	}
+
+	pub(super) fn insert_segment(&mut self, id: SegmentId) {
+		self.segments.insert(id);
+	}
+
+	/// Adds everything in `other` to this selection.
+	pub(super) fn extend(&mut self, other: Selection) {
+		self.stations.extend(other.stations);
+		self.segments.extend(other.segments);
+	}
+
+	/// Selects everything in `other` that isn't selected, and deselects
+	/// everything that is.
+	pub(super) fn toggle(&mut self, other: Selection) {
+		for id in other.stations {
+			if !self.stations.remove(&id) {
+				self.stations.insert(id);
+			}
+		}
+
+		for id in other.segments {
+			if !self.segments.remove(&id) {
+				self.segments.insert(id);
+			}
+		}
+	}
+
+	/// Forgets anything that's no longer on the map, e.g. after it was
+	/// undone.
+	pub(crate) fn retain(&mut self, map: &Map) {
+		self.stations.retain(|&id| map.find_station(id).is_some());
+		self.segments.retain(|&id| map.find_segment(id).is_some());
+	}
+}
+
+impl Map {
+	/// Moves every selected station, along with the waypoints of segments
+	/// between them. Returns the edit that undoes it.
+	pub(crate) fn move_selection(
+		&mut self,
+		selection: &Selection,
+		by: Vector,
+	) -> Edit {
+		let mut undo: Vec<_> = selection
+			.stations
+			.iter()
+			.map(|&id| {
+				let index = self.station_index(id);
+				let position = self.graph[index].position + by;
+				self.move_station(index, position)
+			})
+			.collect();
+
+		let between: Vec<_> = self
+			.segment_indices()
+			.filter(|&index| {
+				let (start, end) = self
+					.graph
+					.edge_endpoints(index)
+					.expect("Edge index from iteration not in graph");
+				!matches!(
+					self.segment(index).interpolation,
+					Interpolation::Auto(..)
+				) && selection.contains_station(self.graph[start].id)
+					&& selection.contains_station(self.graph[end].id)
+			})
+			.map(|index| self.segment(index).clone())
+			.collect();
+
+		for mut segment in between {
+			segment.interpolation = segment.interpolation.moved(by);
+			undo.push(self.replace_segment(segment));
+		}
+
+		undo.reverse();
+		Edit::Group(undo)
+	}
+
+	/// Removes every selected station and segment, along with any segments
+	/// connected to the selected stations. Returns the edit that undoes it.
+	pub(crate) fn remove_selection(&mut self, selection: &Selection) -> Edit {
+		let mut undo = Vec::new();
+
+		for &id in &selection.segments {
+			undo.push(self.delete_segment(id));
+		}
+
+		for &id in &selection.stations {
+			undo.push(self.delete_station(id));
+		}
+
+		undo.reverse();
+		Edit::Group(undo)
+	}
+
+	/// Moves every selected segment to another line, returning the edit that
+	/// undoes it. Segments are left alone where the line already connects
+	/// their stations.
+	pub(crate) fn recolor_selection(
+		&mut self,
+		selection: &Selection,
+		line: LineIndex,
+	) -> Edit {
+		let mut undo = Vec::new();
+
+		for &id in &selection.segments {
+			let index = self.segment_index(id);
+			let (start, end) = self
+				.graph
+				.edge_endpoints(index)
+				.expect("Segment index from lookup not in graph");
+
+			let taken = self
+				.segments_between(start, end)
+				.any(|(_, segment)| segment.line == line);
+			if taken {
+				continue;
+			}
+
+			let mut segment = self.segment(index).clone();
+			segment.line = line;
+
+			// The segment is recreated going the same way, with the same ID
+			let (start, end) = (self.graph[start].id, self.graph[end].id);
+			undo.push(self.delete_segment(id));
+			undo.push(self.insert_segment(start, end, segment));
+		}
+
+		undo.reverse();
+		Edit::Group(undo)
+	}
+
+	/// The selection along with every segment of the same line that
+	/// carries on from a selected segment, up to where the line ends or
+	/// branches.
+	pub(crate) fn runs(&self, selection: &Selection) -> Selection {
+		let mut runs = selection.clone();
+
+		for &id in &selection.segments {
+			let index = self.segment_index(id);
+			let (start, end) = self
+				.graph
+				.edge_endpoints(index)
+				.expect("Segment index from lookup not in graph");
+
+			for mut station in [start, end] {
+				let mut segment = index;
+
+				while let Some((next, from)) = self.continuing(segment, station)
+				{
+					// Lines running in a loop come back around
+					if !runs.segments.insert(self.segment(next).id) {
+						break;
+					}
+
+					let (a, b) = self
+						.graph
+						.edge_endpoints(next)
+						.expect("Edge index from graph not in graph");
+					station = if a == from { b } else { a };
+					segment = next;
+				}
+			}
+		}
+
+		runs
+	}
+
+	/// How every selected segment's path is worked out, if they're all the
+	/// same.
+	pub(crate) fn selection_interpolation(
+		&self,
+		selection: &Selection,
+	) -> Option<Interpolation> {
+		let mut interpolations = selection
+			.segments
+			.iter()
+			.map(|&id| &self.segment(self.segment_index(id)).interpolation);
+
+		let first = interpolations.next()?;
+		interpolations.all(|i| i == first).then(|| first.clone())
+	}
+
+	/// Changes how each selected segment's path is worked out, returning the
+	/// edit that undoes it.
+	pub(crate) fn interpolate_selection(
+		&mut self,
+		selection: &Selection,
+		f: impl Fn(Interpolation) -> Interpolation,
+	) -> Edit {
+		let mut undo: Vec<_> = selection
+			.segments
+			.iter()
+			.map(|&id| {
+				let mut segment = self.segment(self.segment_index(id)).clone();
+				segment.interpolation = f(segment.interpolation);
+				self.replace_segment(segment)
+			})
+			.collect();
+
+		undo.reverse();
+		Edit::Group(undo)
+	}
+
+	/// The turn radius of every selected segment, if they're all the same,
+	/// or `None` inside if they're all using the map's.
+	pub(crate) fn selection_turn_radius(
+		&self,
+		selection: &Selection,
+	) -> Option<Option<f32>> {
+		let mut radii = selection
+			.segments
+			.iter()
+			.map(|&id| self.segment(self.segment_index(id)).turn_radius);
+
+		let first = radii.next()?;
+		radii.all(|r| r == first).then_some(first)
+	}
+
+	/// Gives each selected segment its own turn radius, or the map's if
+	/// `radius` is `None`. Returns the edit that undoes it.
+	pub(crate) fn set_selection_turn_radius(
+		&mut self,
+		selection: &Selection,
+		radius: Option<f32>,
+	) -> Edit {
+		let mut undo: Vec<_> = selection
+			.segments
+			.iter()
+			.map(|&id| {
+				let mut segment = self.segment(self.segment_index(id)).clone();
+				segment.turn_radius = radius;
+				self.replace_segment(segment)
+			})
+			.collect();
+
+		undo.reverse();
+		Edit::Group(undo)
+	}
+}
@@ -0,0 +1,623 @@
+// Copyright (C) 2022  ThatsNoMoon
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of version 3 of the GNU Affero General Public License as published
+// by the Free Software Foundation.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU Affero General Public License
+// for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use super::{Edit, LineIndex, Map, StationId};
+
+/// One way a line runs through the map, as the stations it calls at in
+/// order. Lines that branch have a service for each branch, sharing the
+/// stations on the trunk, and services running in a loop end where they
+/// start.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Service {
+	stations: Vec<StationId>,
+}
+
+impl Service {
+	pub(super) fn new(stations: Vec<StationId>) -> Self {
+		Self { stations }
+	}
+
+	pub(crate) fn stations(&self) -> &[StationId] {
+		&self.stations
+	}
+
+	pub(crate) fn is_loop(&self) -> bool {
+		self.stations.len() > 2 && self.stations.first() == self.stations.last()
+	}
+
+	/// Whether the service starts or finishes at a station. Loops don't have
+	/// ends.
+	fn ends_at(&self, station: StationId) -> bool {
+		!self.is_loop()
+			&& (self.stations.first() == Some(&station)
+				|| self.stations.last() == Some(&station))
+	}
+
+	/// Where the service runs directly between two stations, either way.
+	fn runs_between(&self, a: StationId, b: StationId) -> Option<usize> {
+		self.stations
+			.windows(2)
+			.position(|w| (w[0], w[1]) == (a, b) || (w[0], w[1]) == (b, a))
+	}
+
+	/// Whether all of this service is run by `other` too, either way.
+	fn is_part_of(&self, other: &Service) -> bool {
+		let len = self.stations.len();
+		let reversed: Vec<_> = self.stations.iter().rev().copied().collect();
+
+		other
+			.stations
+			.windows(len)
+			.any(|w| w == self.stations.as_slice() || w == reversed.as_slice())
+	}
+}
+
+/// Removes services run entirely by another service, and all but the first
+/// of any that are the same.
+fn drop_redundant(services: &mut Vec<Service>) {
+	let mut i = 0;
+	while i < services.len() {
+		let redundant = services.iter().enumerate().any(|(j, other)| {
+			j != i
+				&& services[i].is_part_of(other)
+				&& (services[i] != *other || j < i)
+		});
+
+		if redundant {
+			services.remove(i);
+		} else {
+			i += 1;
+		}
+	}
+}
+
+impl Map {
+	pub(crate) fn services(&self, line: LineIndex) -> &[Service] {
+		&self.lines[line as usize].services
+	}
+
+	/// The stations a line starts or finishes at, in the order its services
+	/// are listed.
+	pub(crate) fn termini(&self, line: LineIndex) -> Vec<StationId> {
+		let mut termini = Vec::new();
+
+		for service in self.services(line) {
+			if service.is_loop() {
+				continue;
+			}
+
+			for &end in [service.stations.first(), service.stations.last()]
+				.into_iter()
+				.flatten()
+			{
+				if !termini.contains(&end) {
+					termini.push(end);
+				}
+			}
+		}
+
+		termini
+	}
+
+	/// Every station a line calls at, in the order its services do. Stations
+	/// shared by several services are only listed the first time.
+	pub(crate) fn line_stations(&self, line: LineIndex) -> Vec<StationId> {
+		let mut stations = Vec::new();
+
+		for service in self.services(line) {
+			for &station in &service.stations {
+				if !stations.contains(&station) {
+					stations.push(station);
+				}
+			}
+		}
+
+		stations
+	}
+
+	/// Turns every service of a line around, returning the edit that undoes
+	/// it.
+	pub(crate) fn reverse_services(&mut self, line: LineIndex) -> Edit {
+		let services = self
+			.services(line)
+			.iter()
+			.map(|service| {
+				Service::new(service.stations.iter().rev().copied().collect())
+			})
+			.collect();
+
+		self.replace_services(line, services)
+	}
+
+	pub(super) fn replace_services(
+		&mut self,
+		line: LineIndex,
+		services: Vec<Service>,
+	) -> Edit {
+		let previous = std::mem::replace(
+			&mut self.lines[line as usize].services,
+			services,
+		);
+
+		Edit::ReplaceServices(line, previous)
+	}
+
+	/// Updates a line's services for a segment added between `a` and `b`,
+	/// returning how they were before.
+	///
+	/// Services that end at either station are carried on to the other, or
+	/// joined up if both do. Otherwise, a segment from partway along a
+	/// service starts a branch, which follows the service up to there. Lines
+	/// carry on through complexes, so this goes for services ending or
+	/// running anywhere in either station's complex too, which are run over
+	/// its transfers to the station.
+	pub(super) fn connect_services(
+		&mut self,
+		line: LineIndex,
+		a: StationId,
+		b: StationId,
+	) -> Vec<Service> {
+		let previous = self.services(line).to_vec();
+		let mut services = previous.clone();
+
+		for station in [a, b] {
+			for service in &mut services {
+				self.run_to(service, station);
+			}
+		}
+
+		let at_a: Vec<_> = (0..services.len())
+			.filter(|&i| services[i].ends_at(a))
+			.collect();
+		let at_b: Vec<_> = (0..services.len())
+			.filter(|&i| services[i].ends_at(b))
+			.collect();
+
+		if let Some(&i) = at_a.iter().find(|i| at_b.contains(i)) {
+			// Both ends of one service, which becomes a loop
+			let first = services[i].stations[0];
+			services[i].stations.push(first);
+		} else if let (Some(&i), Some(&j)) = (at_a.first(), at_b.first()) {
+			let mut from_a = services[i].stations.clone();
+			let mut from_b = services[j].stations.clone();
+
+			// Service `i` keeps going the same way
+			let stations = if from_a.last() == Some(&a) {
+				if from_b[0] != b {
+					from_b.reverse();
+				}
+				from_a.extend(from_b);
+				from_a
+			} else {
+				if from_b.last() != Some(&b) {
+					from_b.reverse();
+				}
+				from_b.extend(from_a);
+				from_b
+			};
+
+			services[i] = Service::new(stations);
+			services.remove(j);
+		} else if !at_a.is_empty() || !at_b.is_empty() {
+			let (from, to, at) = if at_a.is_empty() {
+				(b, a, at_b)
+			} else {
+				(a, b, at_a)
+			};
+
+			for i in at {
+				let stations = &mut services[i].stations;
+				if stations.last() == Some(&from) {
+					stations.push(to);
+				} else {
+					stations.insert(0, to);
+				}
+			}
+		} else {
+			let (near_a, near_b) = (self.complex_ids(a), self.complex_ids(b));
+			let branch = services.iter().find_map(|service| {
+				service.stations.iter().enumerate().find_map(|(i, s)| {
+					if near_a.contains(s) {
+						Some((service, i, a, b))
+					} else if near_b.contains(s) {
+						Some((service, i, b, a))
+					} else {
+						None
+					}
+				})
+			});
+
+			let stations = match branch {
+				Some((service, i, from, to)) => {
+					let mut stations = service.stations[..=i].to_vec();
+					let path = self.transfer_path(
+						self.station_index(stations[i]),
+						self.station_index(from),
+					);
+					stations.extend(
+						path.into_iter().flatten().map(|s| self.graph[s].id),
+					);
+					stations.push(to);
+					stations
+				}
+				None => vec![a, b],
+			};
+
+			services.push(Service::new(stations));
+		}
+
+		self.lines[line as usize].services = services;
+		previous
+	}
+
+	/// Runs a service on over transfers to `station`, if one of its ends is
+	/// elsewhere in the station's complex.
+	fn run_to(&self, service: &mut Service, station: StationId) {
+		if service.is_loop() || service.stations.contains(&station) {
+			return;
+		}
+
+		let to = self.station_index(station);
+		let path = |from: StationId| {
+			let path = self.transfer_path(self.find_station(from)?, to)?;
+			Some(
+				path.into_iter()
+					.map(|s| self.graph[s].id)
+					.collect::<Vec<_>>(),
+			)
+		};
+
+		let stations = &mut service.stations;
+		if let Some(path) = path(stations[stations.len() - 1]) {
+			stations.extend(path);
+		} else if let Some(path) = path(stations[0]) {
+			stations.splice(0..0, path.into_iter().rev());
+		}
+	}
+
+	/// Every station in the same complex as `station`, by ID.
+	fn complex_ids(&self, station: StationId) -> Vec<StationId> {
+		self.complex(self.station_index(station))
+			.into_iter()
+			.map(|s| self.graph[s].id)
+			.collect()
+	}
+
+	/// Takes any transfers off the ends of a service's stations, which are
+	/// left there when the segment beyond them is taken away.
+	fn trim_transfers(&self, mut stations: Vec<StationId>) -> Vec<StationId> {
+		let transfer = |a, b| match (self.find_station(a), self.find_station(b))
+		{
+			(Some(a), Some(b)) => self.transfer_between(a, b).is_some(),
+			_ => false,
+		};
+
+		while stations.len() >= 2
+			&& transfer(
+				stations[stations.len() - 2],
+				stations[stations.len() - 1],
+			) {
+			stations.pop();
+		}
+		while stations.len() >= 2 && transfer(stations[0], stations[1]) {
+			stations.remove(0);
+		}
+
+		stations
+	}
+
+	/// Updates a line's services for the segment or transfer between `a` and
+	/// `b` being removed, returning how they were before. Services running
+	/// over it are cut in two, and loops are opened up there.
+	pub(super) fn disconnect_services(
+		&mut self,
+		line: LineIndex,
+		a: StationId,
+		b: StationId,
+	) -> Vec<Service> {
+		let previous = self.services(line).to_vec();
+		let mut services = Vec::new();
+
+		for service in &previous {
+			let i = match service.runs_between(a, b) {
+				Some(i) => i,
+				None => {
+					services.push(service.clone());
+					continue;
+				}
+			};
+
+			let stations = &service.stations;
+			let parts = if service.is_loop() {
+				let ring = &stations[..stations.len() - 1];
+				let mut opened = ring[i + 1..].to_vec();
+				opened.extend_from_slice(&ring[..=i]);
+				vec![opened]
+			} else {
+				vec![stations[..=i].to_vec(), stations[i + 1..].to_vec()]
+			};
+
+			services.extend(
+				parts
+					.into_iter()
+					.map(|part| self.trim_transfers(part))
+					.filter(|part| part.len() >= 2)
+					.map(Service::new),
+			);
+		}
+
+		// What's left of a branch's trunk is still run by the other
+		// branches
+		drop_redundant(&mut services);
+
+		self.lines[line as usize].services = services;
+		previous
+	}
+
+	/// How a line's services run once a station is taken out of them, with
+	/// each calling at the stations either side of it one after the other
+	/// where it's between two of the line's segments. Services reaching it
+	/// over a transfer are cut there instead. Also returns each pair of
+	/// stations that end up next to each other.
+	pub(super) fn services_without(
+		&self,
+		line: LineIndex,
+		station: StationId,
+	) -> (Vec<Service>, Vec<(StationId, StationId)>) {
+		let segment = |a, b| {
+			let (a, b) = (self.station_index(a), self.station_index(b));
+			self.line_segment(line, a, b).is_some()
+		};
+
+		let mut services = Vec::new();
+		let mut pairs = Vec::new();
+
+		for service in self.services(line) {
+			let is_loop = service.is_loop();
+			let stations = if is_loop {
+				&service.stations[..service.stations.len() - 1]
+			} else {
+				&service.stations[..]
+			};
+			let len = stations.len();
+
+			// The parts of the service either side of where it's cut
+			let mut parts = vec![Vec::new()];
+
+			for (i, &s) in stations.iter().enumerate() {
+				if s != station {
+					parts.last_mut().expect("No parts").push(s);
+					continue;
+				}
+
+				let neighbours = if is_loop {
+					Some((
+						stations[(i + len - 1) % len],
+						stations[(i + 1) % len],
+					))
+				} else if i > 0 && i + 1 < len {
+					Some((stations[i - 1], stations[i + 1]))
+				} else {
+					None
+				};
+
+				match neighbours {
+					Some((a, b)) if segment(a, s) && segment(s, b) => {
+						if a != b
+							&& !pairs.contains(&(a, b))
+							&& !pairs.contains(&(b, a))
+						{
+							pairs.push((a, b));
+						}
+					}
+					_ => parts.push(Vec::new()),
+				}
+			}
+
+			let closed = is_loop && parts.len() == 1;
+			if is_loop && !closed {
+				// Loops are opened up where they're cut, so the last part
+				// carries on into the first
+				let last = parts.pop().expect("No parts");
+				parts[0].splice(0..0, last);
+			}
+
+			for part in parts {
+				let mut remaining = self.trim_transfers(part);
+				// Running out to the station and back just turns around
+				// sooner
+				remaining.dedup();
+
+				if closed && remaining.len() >= 3 {
+					remaining.push(remaining[0]);
+				}
+				if remaining.len() >= 2 {
+					services.push(Service::new(remaining));
+				}
+			}
+		}
+
+		drop_redundant(&mut services);
+
+		(services, pairs)
+	}
+
+	/// Works out the services of every line from its segments, for maps
+	/// that don't have any yet.
+	pub(super) fn rebuild_services(&mut self) {
+		for line in &mut self.lines {
+			line.services.clear();
+		}
+
+		let segments: Vec<_> = self
+			.segment_indices()
+			.map(|index| {
+				let (a, b) = self
+					.graph
+					.edge_endpoints(index)
+					.expect("Edge index from iteration not in graph");
+				(self.segment(index).line, self.graph[a].id, self.graph[b].id)
+			})
+			.collect();
+
+		for (line, a, b) in segments {
+			self.connect_services(line, a, b);
+		}
+	}
+
+	/// Whether every service runs over segments on its line, or transfers,
+	/// between at least two stations, and every segment is run by a service.
+	pub(super) fn services_match(&self) -> bool {
+		let connected =
+			|line, a, b| match (self.find_station(a), self.find_station(b)) {
+				(Some(a), Some(b)) => {
+					self.line_segment(line, a, b).is_some()
+						|| self.transfer_between(a, b).is_some()
+				}
+				_ => false,
+			};
+
+		let valid = self.lines.iter().enumerate().all(|(line, l)| {
+			l.services.iter().all(|service| {
+				service.stations.len() >= 2
+					&& service
+						.stations
+						.windows(2)
+						.all(|w| connected(line as LineIndex, w[0], w[1]))
+			})
+		});
+
+		valid
+			&& self.segment_indices().all(|index| {
+				let (a, b) = self
+					.graph
+					.edge_endpoints(index)
+					.expect("Edge index from iteration not in graph");
+				let (a, b) = (self.graph[a].id, self.graph[b].id);
+				self.services(self.segment(index).line)
+					.iter()
+					.any(|service| service.runs_between(a, b).is_some())
+			})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::map::tests::{services, TestMap};
+
+	/// The stations at either end of each segment, by ID, and its line.
+	fn segments(map: &Map) -> Vec<(u32, u32, LineIndex)> {
+		let mut segments: Vec<_> = map
+			.segment_indices()
+			.map(|index| {
+				let (a, b) = map.graph.edge_endpoints(index).unwrap();
+				let (a, b) = (map.graph[a].id.0, map.graph[b].id.0);
+				(a.min(b), a.max(b), map.segment(index).line)
+			})
+			.collect();
+		segments.sort_unstable();
+		segments
+	}
+
+	/// Removes a station, checking that undoing it puts everything back.
+	fn remove(map: &mut Map, station: u32) {
+		let (before, services_before) = (segments(map), services(map, 0));
+
+		let index = map.station_index(StationId(station));
+		let undo = map.remove_station(index);
+		assert!(map.services_match());
+
+		let mut undone = map.clone();
+		let _ = undo.apply(&mut undone);
+		assert_eq!(segments(&undone), before);
+		assert_eq!(services(&undone, 0), services_before);
+		assert!(undone.services_match());
+	}
+
+	#[test]
+	fn through_station() {
+		let mut map = TestMap::new(&[(0.0, 0.0), (100.0, 0.0), (200.0, 0.0)])
+			.line(0, &[0, 1, 2])
+			.build();
+		assert_eq!(services(&map, 0), [[0, 1, 2]]);
+
+		remove(&mut map, 1);
+		assert_eq!(segments(&map), [(0, 2, 0)]);
+		assert_eq!(services(&map, 0), [[0, 2]]);
+	}
+
+	#[test]
+	fn branch_point() {
+		let stations =
+			[(0.0, 0.0), (100.0, 0.0), (200.0, -100.0), (200.0, 100.0)];
+		let mut map = TestMap::new(&stations)
+			.line(0, &[0, 1, 2])
+			.line(0, &[1, 3])
+			.build();
+		assert_eq!(services(&map, 0), [[0, 1, 2], [0, 1, 3]]);
+
+		// Both branches stay joined to the trunk
+		remove(&mut map, 1);
+		assert_eq!(segments(&map), [(0, 2, 0), (0, 3, 0)]);
+		assert_eq!(services(&map, 0), [[0, 2], [0, 3]]);
+	}
+
+	#[test]
+	fn branch() {
+		let stations = [
+			(0.0, 0.0),
+			(100.0, 0.0),
+			(200.0, -100.0),
+			(200.0, 100.0),
+			(300.0, 100.0),
+		];
+		let mut map = TestMap::new(&stations)
+			.line(0, &[0, 1, 2])
+			.line(0, &[1, 3, 4])
+			.build();
+
+		// Only the branch through the station is joined up
+		remove(&mut map, 3);
+		assert_eq!(segments(&map), [(0, 1, 0), (1, 2, 0), (1, 4, 0)]);
+		assert_eq!(services(&map, 0), [[0, 1, 2], [0, 1, 4]]);
+	}
+
+	#[test]
+	fn loop_station() {
+		let stations = [(0.0, 0.0), (100.0, 0.0), (100.0, 100.0), (0.0, 100.0)];
+		let mut map = TestMap::new(&stations).line(0, &[0, 1, 2, 3, 0]).build();
+		assert!(map.services(0)[0].is_loop());
+
+		// The loop stays closed, around the stations left
+		remove(&mut map, 1);
+		assert_eq!(segments(&map), [(0, 2, 0), (0, 3, 0), (2, 3, 0)]);
+		assert_eq!(map.services(0).len(), 1);
+		let service = &map.services(0)[0];
+		assert!(service.is_loop());
+		assert_eq!(service.stations.len(), 4);
+	}
+
+	#[test]
+	fn terminus() {
+		let mut map = TestMap::new(&[(0.0, 0.0), (100.0, 0.0), (200.0, 0.0)])
+			.line(0, &[0, 1, 2])
+			.build();
+
+		// Nothing is joined where the line ends
+		remove(&mut map, 2);
+		assert_eq!(segments(&map), [(0, 1, 0)]);
+		assert_eq!(services(&map, 0), [[0, 1]]);
+	}
+}
@@ -0,0 +1,400 @@
+// Copyright (C) 2022  ThatsNoMoon
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of version 3 of the GNU Affero General Public License as published
+// by the Free Software Foundation.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU Affero General Public License
+// for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Finding what's near a point or within an area without looking at
+//! everything on the map. Stations and the boxes around segments are sorted
+//! into the squares of a coarse grid they overlap, and kept up to date as the
+//! map is edited, along with station names and transfers. Where each station
+//! and segment is in the graph is kept by ID as well, since petgraph moves
+//! things around when they're removed.
+
+use std::{
+	collections::{HashMap, HashSet},
+	hash::Hash,
+};
+
+use iced::{Point, Rectangle, Vector};
+use petgraph::{graph::EdgeIndex, visit::EdgeRef};
+
+use super::{
+	bundle::path,
+	render::{segment_shape, GRID_SIZE, LINE_WIDTH, STATION_OUTER_SIZE},
+	Index, Map, SegmentId, StationId, StationIndex,
+};
+
+/// The size of the squares things are sorted into.
+const BUCKET_SIZE: f32 = GRID_SIZE * 4.0;
+
+type Cell = (i32, i32);
+
+#[derive(Debug, Clone, Default)]
+pub(super) struct SpatialIndex {
+	stations: Buckets<StationIndex>,
+	segments: Buckets<EdgeIndex<Index>>,
+	/// Every station's label, whether it's shown or not.
+	labels: Buckets<StationIndex>,
+	transfers: Buckets<EdgeIndex<Index>>,
+	station_ids: HashMap<StationId, StationIndex>,
+	segment_ids: HashMap<SegmentId, EdgeIndex<Index>>,
+}
+
+/// Things sorted into the squares they overlap.
+#[derive(Debug, Clone)]
+struct Buckets<T> {
+	cells: HashMap<Cell, Vec<T>>,
+	/// The first and last square each thing is in.
+	areas: HashMap<T, (Cell, Cell)>,
+}
+
+impl<T> Default for Buckets<T> {
+	fn default() -> Self {
+		Self {
+			cells: HashMap::new(),
+			areas: HashMap::new(),
+		}
+	}
+}
+
+impl<T: Copy + Eq + Hash> Buckets<T> {
+	/// Puts something in every square between two corners, taking it out of
+	/// wherever it was before.
+	fn insert(&mut self, item: T, min: Point, max: Point) {
+		self.remove(item);
+
+		let area = (cell(min), cell(max));
+		for cell in cells(area) {
+			self.cells.entry(cell).or_default().push(item);
+		}
+		self.areas.insert(item, area);
+	}
+
+	fn remove(&mut self, item: T) {
+		let area = match self.areas.remove(&item) {
+			Some(area) => area,
+			None => return,
+		};
+
+		for cell in cells(area) {
+			if let Some(items) = self.cells.get_mut(&cell) {
+				items.retain(|&other| other != item);
+				if items.is_empty() {
+					self.cells.remove(&cell);
+				}
+			}
+		}
+	}
+
+	/// Files something under another key, after the graph moved it to a
+	/// different index.
+	fn rename(&mut self, from: T, to: T) {
+		let area = match self.areas.remove(&from) {
+			Some(area) => area,
+			None => return,
+		};
+
+		for cell in cells(area) {
+			for item in self.cells.get_mut(&cell).into_iter().flatten() {
+				if *item == from {
+					*item = to;
+				}
+			}
+		}
+		self.areas.insert(to, area);
+	}
+
+	/// Everything in the squares an area overlaps, which includes everything
+	/// in the area, but maybe more.
+	fn query(&self, area: Rectangle) -> Vec<T> {
+		let area = (
+			cell(area.position()),
+			cell(area.position() + Vector::new(area.width, area.height)),
+		);
+
+		let mut seen = HashSet::new();
+		let mut found = Vec::new();
+		for cell in cells(area) {
+			for &item in self.cells.get(&cell).into_iter().flatten() {
+				if seen.insert(item) {
+					found.push(item);
+				}
+			}
+		}
+
+		found
+	}
+}
+
+fn cell(p: Point) -> Cell {
+	(
+		(p.x / BUCKET_SIZE).floor() as i32,
+		(p.y / BUCKET_SIZE).floor() as i32,
+	)
+}
+
+fn cells((min, max): (Cell, Cell)) -> impl Iterator<Item = Cell> {
+	(min.0..=max.0).flat_map(move |x| (min.1..=max.1).map(move |y| (x, y)))
+}
+
+impl SpatialIndex {
+	pub(super) fn station(&self, id: StationId) -> Option<StationIndex> {
+		self.station_ids.get(&id).copied()
+	}
+
+	pub(super) fn segment(&self, id: SegmentId) -> Option<EdgeIndex<Index>> {
+		self.segment_ids.get(&id).copied()
+	}
+}
+
+impl Map {
+	/// The stations that might be within an area.
+	pub(super) fn stations_in(&self, area: Rectangle) -> Vec<StationIndex> {
+		self.spatial.stations.query(area)
+	}
+
+	/// The segments that might be drawn within an area.
+	pub(super) fn segments_in(&self, area: Rectangle) -> Vec<EdgeIndex<Index>> {
+		self.spatial.segments.query(area)
+	}
+
+	/// The stations whose labels might be within an area, if they're shown.
+	pub(super) fn labels_in(&self, area: Rectangle) -> Vec<StationIndex> {
+		self.spatial.labels.query(area)
+	}
+
+	/// The transfers that might be drawn within an area.
+	pub(super) fn transfers_in(
+		&self,
+		area: Rectangle,
+	) -> Vec<EdgeIndex<Index>> {
+		self.spatial.transfers.query(area)
+	}
+
+	/// Sorts everything into the index from scratch.
+	pub(super) fn rebuild_spatial(&mut self) {
+		self.spatial = SpatialIndex::default();
+
+		for index in self.graph.node_indices() {
+			self.index_station(index);
+		}
+		let (segments, transfers): (Vec<_>, Vec<_>) = self
+			.graph
+			.edge_indices()
+			.partition(|&index| self.graph[index].segment().is_some());
+		for index in segments {
+			self.index_segment(index);
+		}
+		for index in transfers {
+			self.index_transfer(index);
+		}
+	}
+
+	/// Sorts a station and its label into the index, which has to be done
+	/// again whenever either moves or the station is renamed.
+	pub(super) fn index_station(&mut self, index: StationIndex) {
+		let station = &self.graph[index];
+		let spatial = &mut self.spatial;
+		spatial
+			.stations
+			.insert(index, station.position, station.position);
+		spatial.station_ids.insert(station.id, index);
+
+		let label = station.label.rect(station.position, &station.name);
+		let corner = label.position() + Vector::new(label.width, label.height);
+		spatial.labels.insert(index, label.position(), corner);
+	}
+
+	/// Takes a station out of the index once it's been removed from the
+	/// graph, which moves the last station into its place.
+	pub(super) fn unindex_station(
+		&mut self,
+		index: StationIndex,
+		id: StationId,
+	) {
+		let spatial = &mut self.spatial;
+		spatial.stations.remove(index);
+		spatial.labels.remove(index);
+		spatial.station_ids.remove(&id);
+
+		let last = StationIndex::new(self.graph.node_count());
+		if last != index {
+			spatial.stations.rename(last, index);
+			spatial.labels.rename(last, index);
+			spatial.station_ids.insert(self.graph[index].id, index);
+		}
+	}
+
+	/// Takes a segment, or a transfer if there's no ID, out of the index once
+	/// it's been removed from the graph, like [`Map::unindex_station`].
+	pub(super) fn unindex_edge(
+		&mut self,
+		index: EdgeIndex<Index>,
+		id: Option<SegmentId>,
+	) {
+		let spatial = &mut self.spatial;
+		spatial.segments.remove(index);
+		spatial.transfers.remove(index);
+		if let Some(id) = id {
+			spatial.segment_ids.remove(&id);
+		}
+
+		// Whichever the last edge is, it's only in one of these
+		let last = EdgeIndex::new(self.graph.edge_count());
+		if last != index {
+			spatial.segments.rename(last, index);
+			spatial.transfers.rename(last, index);
+			if let Some(segment) = self.graph[index].segment() {
+				spatial.segment_ids.insert(segment.id, index);
+			}
+		}
+	}
+
+	/// Sorts a transfer into the squares its marker covers.
+	pub(super) fn index_transfer(&mut self, index: EdgeIndex<Index>) {
+		let (a, b) = self
+			.graph
+			.edge_endpoints(index)
+			.expect("Transfer index not in graph");
+		let (a, b) = (self.graph[a].position, self.graph[b].position);
+
+		let min = Point::new(a.x.min(b.x), a.y.min(b.y));
+		let max = Point::new(a.x.max(b.x), a.y.max(b.y));
+		let padding = Vector::new(STATION_OUTER_SIZE, STATION_OUTER_SIZE);
+		self.spatial
+			.transfers
+			.insert(index, min - padding, max + padding);
+	}
+
+	/// Sorts a segment into the squares around everywhere it could be drawn.
+	fn index_segment(&mut self, index: EdgeIndex<Index>) {
+		let graph = &self.graph;
+		let (start, end) = graph
+			.edge_endpoints(index)
+			.expect("Segment index not in graph");
+
+		// The corners of the path are included as well as the shape, so the
+		// box doesn't need updating when corners are rounded less
+		let corners = path(
+			graph[start].position,
+			graph[end].position,
+			&self.segment(index).interpolation,
+		)
+		.into_iter()
+		.map(|p| (p, 0.0));
+		let extents = segment_shape(self, index, 0.0).extents();
+
+		// Room for the segment to be moved aside by the others between the
+		// same stations
+		let beside = self.segments_between(start, end).count() as f32;
+		let padding = (beside / 2.0 + 1.0) * LINE_WIDTH;
+
+		let mut min = Point::new(f32::INFINITY, f32::INFINITY);
+		let mut max = Point::new(f32::NEG_INFINITY, f32::NEG_INFINITY);
+		for (p, extent) in corners.chain(extents) {
+			let extent = extent + padding;
+			min.x = min.x.min(p.x - extent);
+			min.y = min.y.min(p.y - extent);
+			max.x = max.x.max(p.x + extent);
+			max.y = max.y.max(p.y + extent);
+		}
+
+		self.spatial.segments.insert(index, min, max);
+		self.spatial
+			.segment_ids
+			.insert(self.segment(index).id, index);
+	}
+
+	/// Sorts the segments whose shape depends on where a station is again,
+	/// which are those on the station and those on its neighbours, since
+	/// curves line up with the segments on either side, and the station's
+	/// transfers.
+	pub(super) fn index_around(&mut self, station: StationIndex) {
+		let transfers: Vec<_> = self
+			.graph
+			.edges(station)
+			.filter(|edge| edge.weight().segment().is_none())
+			.map(|edge| edge.id())
+			.collect();
+		for transfer in transfers {
+			self.index_transfer(transfer);
+		}
+
+		let mut segments = Vec::new();
+		for (_, other, _) in self.segments_at(station) {
+			for (next, ..) in self.segments_at(other) {
+				if !segments.contains(&next) {
+					segments.push(next);
+				}
+			}
+		}
+
+		for segment in segments {
+			self.index_segment(segment);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::map::tests::TestMap;
+
+	/// Checks that every station and segment is found by its ID and where it
+	/// is.
+	fn check(map: &Map) {
+		for index in map.graph.node_indices() {
+			let station = &map.graph[index];
+			assert_eq!(map.find_station(station.id), Some(index));
+
+			let at = Rectangle::new(station.position, iced::Size::ZERO);
+			assert!(map.stations_in(at).contains(&index));
+
+			let label = station.label.rect(station.position, &station.name);
+			assert!(map.labels_in(label).contains(&index));
+		}
+		for index in map.segment_indices() {
+			assert_eq!(map.find_segment(map.segment(index).id), Some(index));
+		}
+	}
+
+	#[test]
+	fn removal() {
+		let stations = [(0.0, 0.0), (100.0, 0.0), (200.0, 0.0), (300.0, 0.0)];
+		let mut map = TestMap::new(&stations).line(0, &[0, 1, 2, 3]).build();
+		check(&map);
+
+		// The last station and segment are moved into the removed ones'
+		// places
+		let undo = map.remove_station(StationIndex::new(0));
+		assert_eq!(map.find_station(StationId(0)), None);
+		check(&map);
+
+		let _ = undo.apply(&mut map);
+		check(&map);
+	}
+
+	#[test]
+	fn transfers() {
+		let mut map =
+			TestMap::new(&[(0.0, 0.0), (100.0, 0.0), (200.0, 0.0)]).build();
+		let _ = map.insert_transfer(StationId(1), StationId(2));
+		let middle = Point::new(150.0, 0.0);
+		assert_eq!(map.transfer_at(middle), Some((StationId(1), StationId(2))));
+
+		let _ = map.remove_station(StationIndex::new(0));
+		assert_eq!(map.transfer_at(middle), Some((StationId(1), StationId(2))));
+
+		let _ = map.remove_station(map.station_index(StationId(2)));
+		assert_eq!(map.transfer_at(middle), None);
+	}
+}
@@ -0,0 +1,54 @@
+// Copyright (C) 2022  ThatsNoMoon
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of version 3 of the GNU Affero General Public License as published
+// by the Free Software Foundation.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU Affero General Public License
+// for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use serde::{Deserialize, Serialize};
+
+use super::{render::TURN_RADIUS, Edit, Map};
+
+/// The largest turn radius offered in the editor.
+pub(crate) const MAX_TURN_RADIUS: f32 = 100.0;
+
+/// Settings for how the whole map is drawn.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct Style {
+	/// Radius of the rounding at segments' corners, unless a segment has its
+	/// own. Corners are sharp when it's zero.
+	pub(crate) turn_radius: f32,
+}
+
+impl Default for Style {
+	fn default() -> Self {
+		Self {
+			turn_radius: TURN_RADIUS,
+		}
+	}
+}
+
+impl Map {
+	pub(crate) fn style(&self) -> &Style {
+		&self.style
+	}
+
+	pub(crate) fn set_turn_radius(&mut self, radius: f32) -> Edit {
+		let mut style = self.style;
+		style.turn_radius = radius;
+
+		self.replace_style(style)
+	}
+
+	pub(super) fn replace_style(&mut self, style: Style) -> Edit {
+		Edit::ReplaceStyle(std::mem::replace(&mut self.style, style))
+	}
+}
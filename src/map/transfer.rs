@@ -0,0 +1,353 @@
+// Copyright (C) 2022  ThatsNoMoon
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of version 3 of the GNU Affero General Public License as published
+// by the Free Software Foundation.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU Affero General Public License
+// for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Transfers are passageways between two stations, which aren't on a line
+//! together. They're edges in the graph like segments, but don't belong to a
+//! line. Stations joined by transfers make up a complex, which is drawn and
+//! labelled as one station, and which lines can carry on through.
+
+use std::collections::VecDeque;
+
+use iced::{Point, Rectangle, Size};
+use ordered_float::NotNan;
+use petgraph::{graph::EdgeIndex, visit::EdgeRef};
+
+use super::{
+	geometry::{distance_to_line, magnitude},
+	render::STATION_OUTER_SIZE,
+	Edit, Index, LineIndex, Link, Map, Selection, StationId, StationIndex,
+};
+
+impl Map {
+	/// The stations at either end of each transfer.
+	pub(super) fn transfer_ends(
+		&self,
+	) -> impl Iterator<Item = (StationIndex, StationIndex)> + '_ {
+		self.graph
+			.edge_references()
+			.filter(|edge| edge.weight().segment().is_none())
+			.map(|edge| (edge.source(), edge.target()))
+	}
+
+	/// The transfer between two stations, if there is one.
+	pub(super) fn transfer_between(
+		&self,
+		a: StationIndex,
+		b: StationIndex,
+	) -> Option<EdgeIndex<Index>> {
+		self.graph
+			.edges_connecting(a, b)
+			.find(|edge| edge.weight().segment().is_none())
+			.map(|edge| edge.id())
+	}
+
+	/// The stations joined to `station` by transfers.
+	pub(super) fn transfers_at(
+		&self,
+		station: StationIndex,
+	) -> impl Iterator<Item = StationIndex> + '_ {
+		self.graph
+			.edges(station)
+			.filter(|edge| edge.weight().segment().is_none())
+			.map(move |edge| {
+				if edge.source() == station {
+					edge.target()
+				} else {
+					edge.source()
+				}
+			})
+	}
+
+	/// Every station in the same complex as `station`, including itself.
+	pub(super) fn complex(&self, station: StationIndex) -> Vec<StationIndex> {
+		let mut complex = vec![station];
+
+		let mut i = 0;
+		while i < complex.len() {
+			for other in self.transfers_at(complex[i]) {
+				if !complex.contains(&other) {
+					complex.push(other);
+				}
+			}
+			i += 1;
+		}
+
+		complex
+	}
+
+	/// Whether two stations are in the same complex, so lines shouldn't run
+	/// between them.
+	pub(super) fn same_complex(
+		&self,
+		a: StationIndex,
+		b: StationIndex,
+	) -> bool {
+		self.complex(a).contains(&b)
+	}
+
+	/// The stations passed through going from `from` to `to` over transfers,
+	/// not including `from`, or `None` if they aren't in the same complex.
+	pub(super) fn transfer_path(
+		&self,
+		from: StationIndex,
+		to: StationIndex,
+	) -> Option<Vec<StationIndex>> {
+		let mut came_from = vec![(from, from)];
+		let mut queue = VecDeque::from([from]);
+
+		while let Some(station) = queue.pop_front() {
+			if station == to {
+				let mut path = Vec::new();
+				let mut at = to;
+				while at != from {
+					path.push(at);
+					at = came_from
+						.iter()
+						.find(|&&(s, _)| s == at)
+						.map(|&(_, previous)| previous)
+						.expect("Station on path not reached");
+				}
+				path.reverse();
+				return Some(path);
+			}
+
+			for next in self.transfers_at(station) {
+				if came_from.iter().all(|&(s, _)| s != next) {
+					came_from.push((next, station));
+					queue.push_back(next);
+				}
+			}
+		}
+
+		None
+	}
+
+	/// Whether a station's name is shown. Only the first named station in a
+	/// complex is labelled, for the whole complex.
+	pub(super) fn labelled(&self, index: StationIndex) -> bool {
+		let station = &self.graph[index];
+
+		!station.name.is_empty()
+			&& self.complex(index).into_iter().all(|other| {
+				let other = &self.graph[other];
+				other.id >= station.id || other.name.is_empty()
+			})
+	}
+
+	/// The transfer drawn under `p`, if there is one.
+	pub(super) fn transfer_at(
+		&self,
+		p: Point,
+	) -> Option<(StationId, StationId)> {
+		self.transfers_in(Rectangle::new(p, Size::ZERO))
+			.into_iter()
+			.map(|index| {
+				let (a, b) = self
+					.graph
+					.edge_endpoints(index)
+					.expect("Transfer index not in graph");
+				let (a, b) = (&self.graph[a], &self.graph[b]);
+				let distance = distance_to_line(p, a.position, b.position);
+				((a.id, b.id), distance)
+			})
+			.filter(|&(_, distance)| distance <= STATION_OUTER_SIZE)
+			.min_by_key(|&(_, distance)| NotNan::new(distance).unwrap())
+			.map(|(ends, _)| ends)
+	}
+
+	/// Joins the selected stations into one complex, linking each to the
+	/// closest station it isn't already joined to. Stations with a segment
+	/// between them are left apart, since lines can't run within a complex.
+	/// Returns the edit that undoes it.
+	pub(crate) fn join_stations(&mut self, selection: &Selection) -> Edit {
+		let stations: Vec<_> = selection
+			.stations()
+			.filter_map(|id| self.find_station(id))
+			.collect();
+
+		let mut pairs: Vec<_> = stations
+			.iter()
+			.enumerate()
+			.flat_map(|(i, &a)| {
+				let from = self.graph[a].position;
+				stations[i + 1..].iter().map(move |&b| (a, b, from))
+			})
+			.map(|(a, b, from)| {
+				(a, b, magnitude(self.graph[b].position - from))
+			})
+			.collect();
+		pairs.sort_by_key(|&(.., distance)| NotNan::new(distance).unwrap());
+
+		let mut undo = Vec::new();
+		for (a, b, _) in pairs {
+			let (from, to) = (self.complex(a), self.complex(b));
+			if from.contains(&b) {
+				continue;
+			}
+
+			let linked = from.iter().any(|&a| {
+				to.iter()
+					.any(|&b| self.segments_between(a, b).next().is_some())
+			});
+			if linked {
+				continue;
+			}
+
+			let (a, b) = (self.graph[a].id, self.graph[b].id);
+			undo.push(self.insert_transfer(a, b));
+		}
+
+		undo.reverse();
+		Edit::Group(undo)
+	}
+
+	/// Whether any of the selected stations have transfers.
+	pub(crate) fn selection_has_transfers(
+		&self,
+		selection: &Selection,
+	) -> bool {
+		self.transfer_ends().any(|(a, b)| {
+			selection.contains_station(self.graph[a].id)
+				|| selection.contains_station(self.graph[b].id)
+		})
+	}
+
+	/// Removes every transfer to or from the selected stations, returning the
+	/// edit that undoes it.
+	pub(crate) fn separate_stations(&mut self, selection: &Selection) -> Edit {
+		let transfers: Vec<_> = self
+			.transfer_ends()
+			.map(|(a, b)| (self.graph[a].id, self.graph[b].id))
+			.filter(|&(a, b)| {
+				selection.contains_station(a) || selection.contains_station(b)
+			})
+			.collect();
+
+		let mut undo: Vec<_> = transfers
+			.into_iter()
+			.map(|(a, b)| self.delete_transfer(a, b))
+			.collect();
+
+		undo.reverse();
+		Edit::Group(undo)
+	}
+
+	/// Removes the transfer between two stations, returning the edit that
+	/// undoes it.
+	pub(crate) fn remove_transfer(
+		&mut self,
+		a: StationId,
+		b: StationId,
+	) -> Edit {
+		self.delete_transfer(a, b)
+	}
+
+	pub(super) fn insert_transfer(
+		&mut self,
+		a: StationId,
+		b: StationId,
+	) -> Edit {
+		let (from, to) = (self.station_index(a), self.station_index(b));
+		let index = self.graph.add_edge(from, to, Link::Transfer);
+		self.index_transfer(index);
+		// Lines are followed through complexes to order them
+		self.forget_bundles();
+
+		Edit::DeleteTransfer(a, b)
+	}
+
+	/// Removes a transfer, cutting any services running over it there.
+	pub(super) fn delete_transfer(
+		&mut self,
+		a: StationId,
+		b: StationId,
+	) -> Edit {
+		let (from, to) = (self.station_index(a), self.station_index(b));
+		let index = self
+			.transfer_between(from, to)
+			.expect("No transfer between the stations");
+
+		self.graph.remove_edge(index);
+		// Another segment might have been moved into its place
+		self.unindex_edge(index, None);
+		self.forget_bundles();
+
+		let mut undo = vec![Edit::InsertTransfer(a, b)];
+		for line in 0..self.lines.len() as LineIndex {
+			let services = self.disconnect_services(line, a, b);
+			if services != self.services(line) {
+				undo.push(Edit::ReplaceServices(line, services));
+			}
+		}
+
+		Edit::Group(undo)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::map::tests::{services, TestMap};
+
+	fn select(stations: &[u32]) -> Selection {
+		let mut selection = Selection::default();
+		for &id in stations {
+			selection.insert_station(StationId(id));
+		}
+		selection
+	}
+
+	#[test]
+	fn join_linked() {
+		let mut map = TestMap::new(&[(0.0, 0.0), (100.0, 0.0)])
+			.line(0, &[0, 1])
+			.build();
+
+		let _ = map.join_stations(&select(&[0, 1]));
+		assert_eq!(map.transfer_ends().count(), 0);
+	}
+
+	#[test]
+	fn join_closest() {
+		let mut map =
+			TestMap::new(&[(0.0, 0.0), (10.0, 0.0), (100.0, 0.0)]).build();
+
+		let _ = map.join_stations(&select(&[0, 1, 2]));
+		let mut ends: Vec<_> = map
+			.transfer_ends()
+			.map(|(a, b)| (a.index().min(b.index()), a.index().max(b.index())))
+			.collect();
+		ends.sort_unstable();
+		assert_eq!(ends, [(0, 1), (1, 2)]);
+		assert!(map.same_complex(StationIndex::new(0), StationIndex::new(2)));
+	}
+
+	#[test]
+	fn through_complex() {
+		let stations = [(0.0, 0.0), (100.0, 0.0), (110.0, 0.0), (200.0, 0.0)];
+		let mut map = TestMap::new(&stations).line(0, &[0, 1]).build();
+		let _ = map.join_stations(&select(&[1, 2]));
+		let _ = map.add_segment(0, StationIndex::new(2), StationIndex::new(3));
+		assert_eq!(services(&map, 0), [[0, 1, 2, 3]]);
+		assert!(map.services_match());
+
+		let undo = map.separate_stations(&select(&[1]));
+		assert_eq!(map.transfer_ends().count(), 0);
+		assert_eq!(services(&map, 0), [[0, 1], [2, 3]]);
+
+		let _ = undo.apply(&mut map);
+		assert_eq!(map.transfer_ends().count(), 1);
+		assert_eq!(services(&map, 0), [[0, 1, 2, 3]]);
+	}
+}
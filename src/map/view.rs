@@ -12,196 +12,584 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-use std::f32::consts::PI;
+use std::{
+	cell::{Cell, RefCell},
+	ops::BitOr,
+	time::{Duration, Instant},
+};
 
 use iced::{
+	alignment::{Horizontal, Vertical},
 	canvas::{
-		event::Status, path::Builder, Cache, Cursor, Event, Frame, Geometry,
-		LineCap, LineDash, LineJoin, Path, Stroke,
+		event::Status, Cache, Cursor, Event, Frame, Geometry, LineCap,
+		LineDash, LineJoin, Path, Stroke, Text,
 	},
 	keyboard::{self, KeyCode, Modifiers},
-	mouse,
-	pure::widget::canvas::Program,
-	Color, Point, Rectangle, Vector,
+	mouse, Color, Font, Length, Point, Rectangle, Renderer, Size, Vector,
+};
+use iced_native::{
+	layout::{self, Layout},
+	renderer::Style,
+	Clipboard, Renderer as _, Shell,
+};
+use iced_pure::{
+	widget::tree::{self, Tree},
+	Widget,
 };
 use ordered_float::NotNan;
+use petgraph::graph::EdgeIndex;
 
 use super::{
-	Interpolation, InterpolationDirection, LineIndex, Map, Station,
+	font,
+	geometry::{distance_to_line, magnitude, Arc, PathCommand, Shape},
+	render::{
+		self, Bounds, Cap, Pen, Surface, BACKGROUND_COLOR, GRID_SIZE,
+		LINE_WIDTH, SELECTION_COLOR, SELECTION_WIDTH, STATION_INNER_COLOR,
+		STATION_OUTER_SIZE, WAYPOINT_SIZE,
+	},
+	ExportFormat, Index, Interpolation, LineIndex, Map, SegmentId, Selection,
 	StationIndex,
 };
 use crate::{color::ColorExt, EditMode, Message};
 
 pub(super) struct MapView<'m> {
 	map: &'m Map,
+	selection: &'m Selection,
 	edit_mode: EditMode,
 	selected_line: LineIndex,
+	revision: Revision,
+	/// Whether a text input outside the map has focus, in which case key
+	/// presses are left to it.
+	typing: bool,
 }
 
 impl<'m> MapView<'m> {
 	pub(super) fn new(
 		map: &'m Map,
+		selection: &'m Selection,
 		edit_mode: EditMode,
 		selected_line: LineIndex,
+		revision: Revision,
+		typing: bool,
 	) -> Self {
 		Self {
 			map,
+			selection,
 			edit_mode,
 			selected_line,
+			revision,
+			typing,
 		}
 	}
 }
 
 #[derive(Debug, Default)]
 pub(super) struct ViewState {
-	cache: Cache,
+	layers: RefCell<Layers>,
+	/// The revision the layers were last drawn at, so changes made outside
+	/// of this view (e.g. opening a file) are picked up.
+	revision: Cell<Revision>,
 	dragging: DragState,
-	pan_offset: Vector,
+	camera: Camera,
+	/// When and on what the last click was, to detect double clicks.
+	last_click: Option<(Instant, ClickStart)>,
+	/// Whether something outside of the map was clicked since the map was
+	/// last clicked, in which case key presses are meant for that instead.
+	unfocused: bool,
+	/// The modifier keys currently held, which mouse events don't include.
+	modifiers: Modifiers,
+	/// The lowest and highest station coordinates, which panning is kept
+	/// within, and the revision they were found at.
+	extent: Option<(Revision, Point, Point)>,
+}
+
+impl ViewState {
+	/// The lowest and highest station coordinates, only found again once
+	/// the map has changed.
+	fn extent(&mut self, map: &Map, revision: Revision) -> (Point, Point) {
+		match self.extent {
+			Some((seen, min, max)) if seen == revision => (min, max),
+			_ => {
+				let (min_x, max_x) =
+					min_max(map.graph.node_weights().map(|s| s.position.x));
+				let (min_y, max_y) =
+					min_max(map.graph.node_weights().map(|s| s.position.y));
+				let (min, max) =
+					(Point::new(min_x, min_y), Point::new(max_x, max_y));
+
+				self.extent = Some((revision, min, max));
+				(min, max)
+			}
+		}
+	}
+}
+
+/// The layers the map is drawn in, from the bottom up, each kept until what's
+/// on it changes. What's drawn over the map while dragging isn't kept, since
+/// it changes whenever the cursor moves.
+///
+/// The layers are drawn a margin past the edges of the view, and moved along
+/// with the camera when it's panned, so they're only drawn again once it's
+/// panned past the margin or zoomed.
+#[derive(Debug, Default)]
+struct Layers {
+	grid: Cache,
+	/// Segments, along with the outlines of selected segments beneath them.
+	segments: Cache,
+	/// Stations, along with the outlines of selected stations and the
+	/// waypoints of selected segments.
+	stations: Cache,
+	labels: Cache,
+	/// The camera the layers were drawn for, and the size of the view.
+	drawn: Option<(Camera, Size)>,
+}
+
+impl Layers {
+	/// Clears the layers that changed between two revisions.
+	fn clear_changed(&mut self, from: Revision, to: Revision) {
+		if from.segments != to.segments {
+			self.segments.clear();
+		}
+		if from.stations != to.stations {
+			self.stations.clear();
+		}
+		if from.labels != to.labels {
+			self.labels.clear();
+		}
+	}
+
+	/// Returns the camera the layers are drawn for, clearing them first if
+	/// they can't be moved to where `camera` shows the map.
+	fn follow(&mut self, camera: Camera, size: Size) -> Camera {
+		match self.drawn {
+			Some((drawn, drawn_size))
+				if drawn.zoom == camera.zoom
+					&& drawn_size == size
+					&& (camera.offset.x - drawn.offset.x).abs()
+						<= PAN_MARGIN && (camera.offset.y - drawn.offset.y)
+					.abs() <= PAN_MARGIN =>
+			{
+				drawn
+			}
+			_ => {
+				self.grid.clear();
+				self.segments.clear();
+				self.stations.clear();
+				self.labels.clear();
+				self.drawn = Some((camera, size));
+				camera
+			}
+		}
+	}
+}
+
+/// Which of the map view's layers something is drawn on, so only those are
+/// drawn again when it changes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct Redraw {
+	pub(super) segments: bool,
+	pub(super) stations: bool,
+	pub(super) labels: bool,
 }
 
+impl Redraw {
+	pub(crate) const NONE: Self = Self {
+		segments: false,
+		stations: false,
+		labels: false,
+	};
+
+	pub(crate) const ALL: Self = Self {
+		segments: true,
+		stations: true,
+		labels: true,
+	};
+
+	/// Selected segments and stations are outlined beneath them.
+	pub(crate) const SELECTION: Self = Self {
+		segments: true,
+		stations: true,
+		labels: false,
+	};
+}
+
+impl BitOr for Redraw {
+	type Output = Self;
+
+	fn bitor(self, other: Self) -> Self {
+		Self {
+			segments: self.segments || other.segments,
+			stations: self.stations || other.stations,
+			labels: self.labels || other.labels,
+		}
+	}
+}
+
+/// How many times each of the map view's layers has changed, so the view
+/// knows which to draw again.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct Revision {
+	segments: u64,
+	stations: u64,
+	labels: u64,
+}
+
+impl Revision {
+	pub(crate) fn mark(&mut self, redraw: Redraw) {
+		if redraw.segments {
+			self.segments = self.segments.wrapping_add(1);
+		}
+		if redraw.stations {
+			self.stations = self.stations.wrapping_add(1);
+		}
+		if redraw.labels {
+			self.labels = self.labels.wrapping_add(1);
+		}
+	}
+}
+
+/// Which part of the map is shown, and how large.
 #[derive(Debug, PartialEq, Clone, Copy)]
+struct Camera {
+	/// Where the map's origin is on screen, relative to the top left of the
+	/// view.
+	offset: Vector,
+	zoom: f32,
+}
+
+impl Default for Camera {
+	fn default() -> Self {
+		Self {
+			offset: Vector::new(0.0, 0.0),
+			zoom: 1.0,
+		}
+	}
+}
+
+impl Camera {
+	/// The point on the map shown at `p`, relative to the top left of the
+	/// view.
+	fn to_map(self, p: Point) -> Point {
+		Point::new(
+			(p.x - self.offset.x) / self.zoom,
+			(p.y - self.offset.y) / self.zoom,
+		)
+	}
+
+	/// Zooms in by `factor`, or out if it's less than one, keeping the point
+	/// shown at `p` where it is.
+	fn zoomed(self, factor: f32, p: Point) -> Self {
+		let zoom = (self.zoom * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+		let fixed = self.to_map(p);
+
+		Self {
+			offset: (p - Point::ORIGIN) - (fixed - Point::ORIGIN) * zoom,
+			zoom,
+		}
+	}
+
+	/// Shows all of `bounds` in the middle of a view of the given size.
+	fn fitting(bounds: Bounds, size: Size) -> Self {
+		let zoom = (size.width / bounds.width())
+			.min(size.height / bounds.height())
+			.clamp(MIN_ZOOM, MAX_ZOOM);
+		let center = Point::new(
+			(bounds.min.x + bounds.max.x) / 2.0,
+			(bounds.min.y + bounds.max.y) / 2.0,
+		);
+
+		Self {
+			offset: Vector::new(
+				size.width / 2.0 - center.x * zoom,
+				size.height / 2.0 - center.y * zoom,
+			),
+			zoom,
+		}
+	}
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
 enum DragState {
+	#[default]
 	None,
 	Clicked(ClickStart),
 	Dragging(ClickStart, Option<StationIndex>),
+	/// Selecting everything within the rectangle between two points.
+	Selecting(Point, Point),
 	Panning(Point, Vector),
 }
 
-impl Default for DragState {
-	fn default() -> Self {
-		Self::None
-	}
-}
-
 #[derive(Debug, PartialEq, Clone, Copy)]
 enum ClickStart {
 	Station(StationIndex),
+	/// A station's label, along with where it was clicked and the label's
+	/// offset at the time.
+	Label(StationIndex, Point, Vector),
+	/// One of a selected segment's waypoints, along with where it was
+	/// clicked.
+	Waypoint(SegmentId, usize, Point),
 	Empty(Point),
 }
 
-const STATION_INNER_SIZE: f32 = 15.0;
-const STATION_OUTER_SIZE: f32 = 20.0;
-
-const GRID_SIZE: f32 = 50.0;
+impl ClickStart {
+	/// Whether two clicks were on the same thing, so together they make a
+	/// double click. Clicks on nothing in particular have to be within
+	/// `range` of each other.
+	fn same_target(self, other: Self, range: f32) -> bool {
+		match (self, other) {
+			(
+				Self::Station(a) | Self::Label(a, ..),
+				Self::Station(b) | Self::Label(b, ..),
+			) => a == b,
+			(Self::Waypoint(a, i, _), Self::Waypoint(b, j, _)) => {
+				a == b && i == j
+			}
+			(Self::Empty(a), Self::Empty(b)) => magnitude(a - b) < range,
+			_ => false,
+		}
+	}
+}
 
+/// How far the cursor has to move, on screen, before a click becomes a drag.
 const DRAG_RANGE: f32 = 5.0;
 
-const TURN_RADIUS: f32 = 40.0;
+const MIN_ZOOM: f32 = 0.25;
+const MAX_ZOOM: f32 = 4.0;
+/// How much each step of the scroll wheel or press of a zoom key zooms by.
+const ZOOM_STEP: f32 = 1.25;
+/// How many pixels of scrolling on a touchpad make up one step.
+const SCROLL_STEP: f32 = 50.0;
 
-impl Program<Message> for MapView<'_> {
-	type State = ViewState;
-	fn draw(
-		&self,
-		state: &ViewState,
-		bounds: Rectangle,
-		cursor: Cursor,
-	) -> Vec<Geometry> {
-		let geometry = state.cache.draw(bounds.size(), |frame| {
-			let background = Path::rectangle(Point::ORIGIN, frame.size());
-			frame.fill(&background, Color::from_rgb32(0x19191D));
+const DOUBLE_CLICK_TIME: Duration = Duration::from_millis(400);
 
-			frame.translate(state.pan_offset);
+/// How far past each edge of the view the layers are drawn, on screen, so
+/// they can be moved along with the camera when it's panned.
+const PAN_MARGIN: f32 = 400.0;
 
-			{
-				let v_grid_lines =
-					(bounds.width / GRID_SIZE + 2.0).ceil() as u32;
-				let h_grid_lines =
-					(bounds.height / GRID_SIZE + 2.0).ceil() as u32;
-
-				let stroke = Stroke {
-					color: Color::from_rgb32(0x3d3d4a),
-					width: 2.0,
-					line_cap: LineCap::Butt,
-					line_join: LineJoin::Miter,
-					line_dash: LineDash {
-						segments: &[],
-						offset: 0,
-					},
-				};
+impl<'m> From<MapView<'m>> for iced::pure::Element<'m, Message> {
+	fn from(view: MapView<'m>) -> Self {
+		Self::new(view)
+	}
+}
 
-				let start_x =
-					(-state.pan_offset.x / GRID_SIZE - 1.0).round() * GRID_SIZE;
+impl Widget<Message, Renderer> for MapView<'_> {
+	fn tag(&self) -> tree::Tag {
+		tree::Tag::of::<ViewState>()
+	}
 
-				let top_y = -state.pan_offset.y - GRID_SIZE;
-				let bottom_y = -state.pan_offset.y + bounds.height + GRID_SIZE;
+	fn state(&self) -> tree::State {
+		tree::State::new(ViewState::default())
+	}
 
-				for i in 0..v_grid_lines {
-					let x = start_x + GRID_SIZE * i as f32;
+	fn width(&self) -> Length {
+		Length::Fill
+	}
 
-					let top = Point::new(x, top_y);
-					let bottom = Point::new(x, bottom_y);
+	fn height(&self) -> Length {
+		Length::Fill
+	}
 
-					let line = Path::line(top, bottom);
-					frame.stroke(&line, stroke)
-				}
+	fn layout(
+		&self,
+		_renderer: &Renderer,
+		limits: &layout::Limits,
+	) -> layout::Node {
+		let limits = limits.width(Length::Fill).height(Length::Fill);
+		layout::Node::new(limits.resolve(Size::ZERO))
+	}
 
-				let start_y =
-					(-state.pan_offset.y / GRID_SIZE - 1.0).round() * GRID_SIZE;
+	fn on_event(
+		&mut self,
+		tree: &mut Tree,
+		event: iced_native::Event,
+		layout: Layout<'_>,
+		cursor_position: Point,
+		_renderer: &Renderer,
+		_clipboard: &mut dyn Clipboard,
+		shell: &mut Shell<'_, Message>,
+	) -> Status {
+		let event = match event {
+			iced_native::Event::Mouse(event) => Event::Mouse(event),
+			iced_native::Event::Keyboard(event) => Event::Keyboard(event),
+			_ => return Status::Ignored,
+		};
+
+		let state = tree.state.downcast_mut::<ViewState>();
+		let (status, message) =
+			self.update(state, event, layout.bounds(), cursor(cursor_position));
+		if let Some(message) = message {
+			shell.publish(message);
+		}
 
-				let left_x = -state.pan_offset.x - GRID_SIZE;
-				let right_x = -state.pan_offset.x + bounds.width + GRID_SIZE;
+		status
+	}
 
-				for i in 0..h_grid_lines {
-					let y = start_y + GRID_SIZE * i as f32;
+	fn draw(
+		&self,
+		tree: &Tree,
+		renderer: &mut Renderer,
+		_style: &Style,
+		layout: Layout<'_>,
+		cursor_position: Point,
+		_viewport: &Rectangle,
+	) {
+		let bounds = layout.bounds();
+		if bounds.width < 1.0 || bounds.height < 1.0 {
+			return;
+		}
 
-					let left = Point::new(left_x, y);
-					let right = Point::new(right_x, y);
+		let state = tree.state.downcast_ref::<ViewState>();
+		let layers = self.layers(state, bounds, cursor(cursor_position));
 
-					let line = Path::line(left, right);
-					frame.stroke(&line, stroke)
-				}
+		// The layers reach past the view, so they're cut off at its edges
+		renderer.with_layer(bounds, |renderer| {
+			for (offset, geometry) in layers {
+				let offset = (bounds.position() - Point::ORIGIN) + offset;
+				renderer.with_translation(offset, |renderer| {
+					renderer.draw_primitive(geometry.into_primitive());
+				});
 			}
+		});
+	}
+}
 
-			let graph = &self.map.graph;
+/// Where the cursor is in the window, if it's in it at all.
+fn cursor(position: Point) -> Cursor {
+	if position.x < 0.0 || position.y < 0.0 {
+		Cursor::Unavailable
+	} else {
+		Cursor::Available(position)
+	}
+}
 
-			for index in graph.edge_indices() {
-				let (start, end) = self
-					.map
-					.graph
-					.edge_endpoints(index)
-					.expect("Edge index from iteration not in graph");
+impl MapView<'_> {
+	/// The layers the map is drawn in, from the bottom up, each along with
+	/// where its top left goes in the view.
+	fn layers(
+		&self,
+		state: &ViewState,
+		bounds: Rectangle,
+		cursor: Cursor,
+	) -> Vec<(Vector, Geometry)> {
+		let size = bounds.size();
+		let camera = state.camera;
+
+		let drawn = {
+			let mut layers = state.layers.borrow_mut();
+			let seen = state.revision.replace(self.revision);
+			layers.clear_changed(seen, self.revision);
+			layers.follow(camera, size)
+		};
+		let layers = state.layers.borrow();
+
+		let margin = Vector::new(PAN_MARGIN, PAN_MARGIN);
+		let drawn_size = Size::new(
+			size.width + PAN_MARGIN * 2.0,
+			size.height + PAN_MARGIN * 2.0,
+		);
+		// Where the layers were drawn, to where the camera is now
+		let panned = camera.offset - drawn.offset - margin;
+
+		let in_layer = |frame: &mut Frame| {
+			frame.translate(drawn.offset + margin);
+			frame.scale(drawn.zoom);
+		};
+		let in_view = |frame: &mut Frame| {
+			frame.translate(camera.offset);
+			frame.scale(camera.zoom);
+		};
+
+		// What the layers cover, on the map, so everything else can be
+		// skipped
+		let top_left = drawn.to_map(Point::ORIGIN - margin);
+		let bottom_right =
+			drawn.to_map(Point::new(bounds.width, bounds.height) + margin);
+		let visible = Some(Rectangle::new(
+			top_left,
+			Size::new(bottom_right.x - top_left.x, bottom_right.y - top_left.y),
+		));
+
+		let grid = layers.grid.draw(drawn_size, |frame| {
+			let background = Path::rectangle(Point::ORIGIN, frame.size());
+			frame.fill(&background, Color::from_rgb32(BACKGROUND_COLOR));
+
+			in_layer(frame);
+			render::draw_grid(
+				top_left - Vector::new(GRID_SIZE, GRID_SIZE),
+				bottom_right + Vector::new(GRID_SIZE, GRID_SIZE),
+				frame,
+			);
+		});
 
-				let start = graph[start].position;
-				let end = graph[end].position;
+		let segments = layers.segments.draw(drawn_size, |frame| {
+			in_layer(frame);
+			self.draw_selected_segments(visible, frame);
+			render::draw_segments(self.map, visible, frame);
+		});
 
-				let segment = &graph[index];
+		// The segment being drawn goes under the stations it's drawn between
+		let mut preview = Frame::new(size);
+		in_view(&mut preview);
+		if let (
+			DragState::Dragging(ClickStart::Station(s), _),
+			EditMode::Line,
+			Some(p),
+		) = (state.dragging, self.edit_mode, cursor.position())
+		{
+			render::draw_segment(
+				self.map.graph[s].position,
+				camera.to_map(p - (bounds.position() - Point::ORIGIN)),
+				&Interpolation::default(),
+				self.map.style.turn_radius,
+				self.map.lines[self.selected_line as usize].color,
+				&mut preview,
+			)
+		}
 
-				self.draw_segment(
-					start,
-					end,
-					segment.interpolation,
-					self.map.lines[segment.line as usize].color,
-					frame,
-				);
-			}
-			if let (
-				DragState::Dragging(ClickStart::Station(s), _),
-				EditMode::Line,
-				Some(p),
-			) = (state.dragging, self.edit_mode, cursor.position())
-			{
-				self.draw_segment(
-					graph[s].position,
-					p - (bounds.position() - Point::ORIGIN) - state.pan_offset,
-					Interpolation::Auto(InterpolationDirection::Auto),
-					self.map.lines[self.selected_line as usize].color,
-					frame,
-				)
-			}
+		let stations = layers.stations.draw(drawn_size, |frame| {
+			in_layer(frame);
+			render::draw_stations(self.map, visible, frame);
+			self.draw_selected_stations(frame);
+			self.draw_waypoints(frame);
+		});
 
-			for station in graph.node_weights() {
-				frame.fill(
-					&Path::circle(station.position, STATION_OUTER_SIZE),
-					Color::from_rgb32(0xd8e0ef),
-				);
-				frame.fill(
-					&Path::circle(station.position, STATION_INNER_SIZE),
-					Color::from_rgb32(0x030405),
-				);
-			}
+		let labels = layers.labels.draw(drawn_size, |frame| {
+			in_layer(frame);
+			render::draw_labels(self.map, visible, frame);
 		});
 
-		vec![geometry]
+		let mut overlay = Frame::new(size);
+		in_view(&mut overlay);
+		if let DragState::Selecting(a, b) = state.dragging {
+			let rect = Path::rectangle(
+				Point::new(a.x.min(b.x), a.y.min(b.y)),
+				Size::new((a.x - b.x).abs(), (a.y - b.y).abs()),
+			);
+			overlay.fill(
+				&rect,
+				Color {
+					a: 0.15,
+					..Color::from_rgb32(SELECTION_COLOR)
+				},
+			);
+			overlay.stroke(
+				&rect,
+				Stroke {
+					color: Color::from_rgb32(SELECTION_COLOR),
+					width: 1.0 / camera.zoom,
+					..Stroke::default()
+				},
+			);
+		}
+
+		vec![
+			(panned, grid),
+			(panned, segments),
+			(Vector::new(0.0, 0.0), preview.into_geometry()),
+			(panned, stations),
+			(panned, labels),
+			(Vector::new(0.0, 0.0), overlay.into_geometry()),
+		]
 	}
 
 	fn update(
@@ -215,8 +603,11 @@ impl Program<Message> for MapView<'_> {
 			Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Left)) => {
 				match cursor.position() {
 					Some(p) if bounds.contains(p) => {
-						let panned = (p - (bounds.position() - Point::ORIGIN))
-							- state.pan_offset;
+						state.unfocused = false;
+
+						let panned = state
+							.camera
+							.to_map(p - (bounds.position() - Point::ORIGIN));
 						if self.edit_mode != EditMode::None {
 							state.dragging = if let Some(station) =
 								self.find_station_at(panned)
@@ -225,10 +616,45 @@ impl Program<Message> for MapView<'_> {
 							} else {
 								DragState::Clicked(ClickStart::Empty(panned))
 							};
+						} else if let Some((segment, waypoint)) =
+							self.find_waypoint_at(panned)
+						{
+							state.dragging = DragState::Clicked(
+								ClickStart::Waypoint(segment, waypoint, panned),
+							);
+						} else if let Some(station) =
+							self.find_station_at(panned)
+						{
+							state.dragging = DragState::Clicked(
+								ClickStart::Station(station),
+							);
+
+							// Pressing on a station that isn't selected
+							// selects it, so it can be dragged straight away
+							let id = self.map.graph[station].id;
+							if !state.modifiers.shift()
+								&& !self.selection.contains_station(id)
+							{
+								return (
+									Status::Captured,
+									Some(Message::Select(Selection::station(
+										id,
+									))),
+								);
+							}
+						} else if let Some(station) = self.find_label_at(panned)
+						{
+							let offset = self.map.graph[station].label.offset;
+							state.dragging = DragState::Clicked(
+								ClickStart::Label(station, panned, offset),
+							);
+						} else {
+							state.dragging =
+								DragState::Clicked(ClickStart::Empty(panned));
 						}
 						return (Status::Captured, None);
 					}
-					_ => (),
+					_ => state.unfocused = true,
 				}
 			}
 			Event::Mouse(mouse::Event::ButtonPressed(
@@ -236,43 +662,156 @@ impl Program<Message> for MapView<'_> {
 			)) => {
 				if let Some(p) = cursor.position() {
 					let p = p - (bounds.position() - Point::ORIGIN);
-					state.dragging = DragState::Panning(p, state.pan_offset);
+					state.dragging = DragState::Panning(p, state.camera.offset);
 					return (Status::Captured, None);
 				}
 			}
+			Event::Mouse(mouse::Event::WheelScrolled { delta }) => {
+				if let Some(p) = cursor.position() {
+					if bounds.contains(p) {
+						let steps = match delta {
+							mouse::ScrollDelta::Lines { y, .. } => y,
+							mouse::ScrollDelta::Pixels { y, .. } => {
+								y / SCROLL_STEP
+							}
+						};
+
+						let p = p - (bounds.position() - Point::ORIGIN);
+						state.camera =
+							state.camera.zoomed(ZOOM_STEP.powf(steps), p);
+						return (Status::Captured, None);
+					}
+				}
+			}
 			Event::Mouse(mouse::Event::CursorMoved { position }) => {
 				let p = position - (bounds.position() - Point::ORIGIN);
-				let panned = p - state.pan_offset;
+				let panned = state.camera.to_map(p);
+				let drag_range = DRAG_RANGE / state.camera.zoom;
 				match state.dragging {
-					DragState::Clicked(start_pos) => {
-						state.cache.clear();
-						match start_pos {
-							ClickStart::Station(s) => {
-								let d = magnitude(
-									self.map.graph[s].position - panned,
+					DragState::Clicked(start_pos) => match start_pos {
+						ClickStart::Station(s) => {
+							let d =
+								magnitude(self.map.graph[s].position - panned);
+							if d > drag_range {
+								let inside =
+									(d < STATION_OUTER_SIZE).then_some(s);
+
+								state.dragging =
+									DragState::Dragging(start_pos, inside);
+
+								return (
+									Status::Captured,
+									Some(Message::DragStarted),
 								);
-								if d > DRAG_RANGE {
-									let inside =
-										(d < STATION_OUTER_SIZE).then(|| s);
-
-									state.dragging =
-										DragState::Dragging(start_pos, inside);
-								}
 							}
-							ClickStart::Empty(start) => {
-								let d = magnitude(start - panned);
-								if d > DRAG_RANGE {
-									state.dragging =
-										DragState::Panning(p, state.pan_offset);
-								}
+						}
+						ClickStart::Label(_, start, _)
+						| ClickStart::Waypoint(_, _, start) => {
+							if magnitude(start - panned) > drag_range {
+								state.dragging =
+									DragState::Dragging(start_pos, None);
+
+								return (
+									Status::Captured,
+									Some(Message::DragStarted),
+								);
 							}
 						}
+						ClickStart::Empty(start) => {
+							let d = magnitude(start - panned);
+							if d > drag_range {
+								state.dragging = if self.edit_mode
+									== EditMode::None
+								{
+									DragState::Selecting(start, panned)
+								} else {
+									DragState::Panning(p, state.camera.offset)
+								};
+							}
+						}
+					},
+					DragState::Dragging(
+						ClickStart::Label(station, start, offset),
+						_,
+					) => {
+						return (
+							Status::Captured,
+							Some(Message::MoveLabel(
+								station,
+								offset + (panned - start),
+							)),
+						);
+					}
+					DragState::Dragging(ClickStart::Station(station), _)
+						if self.edit_mode == EditMode::None =>
+					{
+						let graph = &self.map.graph;
+
+						// Stations dragged while holding shift aren't
+						// selected yet
+						let id = graph[station].id;
+						if !self.selection.contains_station(id) {
+							let mut selection = self.selection.clone();
+							selection.insert_station(id);
+							return (
+								Status::Captured,
+								Some(Message::Select(selection)),
+							);
+						}
+
+						let by = render::snap(panned) - graph[station].position;
+						if by == Vector::new(0.0, 0.0) {
+							return (Status::Captured, None);
+						}
+
+						// Stations can't be moved on top of ones that aren't
+						// moving
+						let occupied = self
+							.selection
+							.stations()
+							.filter_map(|id| self.map.find_station(id))
+							.any(|moved| {
+								let to = graph[moved].position + by;
+								self.map
+									.stations_in(around(to, 0.0))
+									.into_iter()
+									.any(|other| {
+										let other = &graph[other];
+										other.position == to
+											&& !self
+												.selection
+												.contains_station(other.id)
+									})
+							});
+
+						if !occupied {
+							return (
+								Status::Captured,
+								Some(Message::MoveSelection(by)),
+							);
+						}
+					}
+					DragState::Dragging(
+						ClickStart::Waypoint(segment, waypoint, _),
+						_,
+					) => {
+						let to = render::snap(panned);
+						let from = self.map.waypoints(segment).get(waypoint);
+						if from.is_some_and(|&from| from != to) {
+							return (
+								Status::Captured,
+								Some(Message::MoveWaypoint(
+									segment, waypoint, to,
+								)),
+							);
+						}
+					}
+					DragState::Selecting(start, _) => {
+						state.dragging = DragState::Selecting(start, panned);
 					}
 					DragState::Dragging(ClickStart::Station(start), inside)
 						if self.edit_mode == EditMode::Line =>
 					{
-						state.cache.clear();
-
 						match inside {
 							None => {
 								if let Some(now_inside) =
@@ -281,10 +820,12 @@ impl Program<Message> for MapView<'_> {
 									if now_inside != start
 										&& !self
 											.map
-											.graph
-											.edges_connecting(start, now_inside)
-											.any(|edge| {
-												edge.weight().line
+											.same_complex(start, now_inside)
+										&& !self
+											.map
+											.segments_between(start, now_inside)
+											.any(|(_, segment)| {
+												segment.line
 													== self.selected_line
 											}) {
 										state.dragging = DragState::Dragging(
@@ -314,26 +855,19 @@ impl Program<Message> for MapView<'_> {
 						}
 					}
 					DragState::Panning(start, initial_offset) => {
-						state.cache.clear();
-						state.pan_offset = initial_offset + (p - start);
-
-						let (min_x, max_x) = min_max(
-							self.map.graph.node_weights().map(|s| s.position.x),
-						);
-						let (min_y, max_y) = min_max(
-							self.map.graph.node_weights().map(|s| s.position.y),
-						);
+						let (min, max) = state.extent(self.map, self.revision);
+						let zoom = state.camera.zoom;
+						let offset = &mut state.camera.offset;
+						*offset = initial_offset + (p - start);
 
-						state.pan_offset.x = state
-							.pan_offset
+						offset.x = offset
 							.x
-							.min(max_x)
-							.max(min_x - bounds.width);
-						state.pan_offset.y = state
-							.pan_offset
+							.min(max.x * zoom)
+							.max(min.x * zoom - bounds.width);
+						offset.y = offset
 							.y
-							.min(max_y)
-							.max(min_y - bounds.height);
+							.min(max.y * zoom)
+							.max(min.y * zoom - bounds.height);
 					}
 					_ => (),
 				}
@@ -341,7 +875,6 @@ impl Program<Message> for MapView<'_> {
 			Event::Mouse(mouse::Event::ButtonReleased(mouse::Button::Left)) => {
 				let dragging = state.dragging;
 
-				state.cache.clear();
 				state.dragging = DragState::None;
 
 				match (self.edit_mode, dragging) {
@@ -349,12 +882,17 @@ impl Program<Message> for MapView<'_> {
 						EditMode::Station,
 						DragState::Clicked(ClickStart::Empty(p)),
 					) => {
-						let x = (p.x / GRID_SIZE).round() * GRID_SIZE;
-						let y = (p.y / GRID_SIZE).round() * GRID_SIZE;
-						let position = Point::new(x, y);
+						if let Some(segment) = self.find_segment_at(p) {
+							let id = self.map.segment(segment).id;
+							return (
+								Status::Captured,
+								Some(Message::SplitSegment(id, p)),
+							);
+						}
+
 						return (
 							Status::Captured,
-							Some(Message::AddStation(Station { position })),
+							Some(Message::AddStation(render::snap(p))),
 						);
 					}
 					(
@@ -366,28 +904,267 @@ impl Program<Message> for MapView<'_> {
 							Some(Message::RemoveStation(s)),
 						)
 					}
+					(
+						EditMode::Remove,
+						DragState::Clicked(ClickStart::Empty(p)),
+					) => {
+						if let Some(segment) = self.find_segment_at(p) {
+							let id = self.map.segment(segment).id;
+							return (
+								Status::Captured,
+								Some(Message::RemoveSegment(id)),
+							);
+						}
+						if let Some((a, b)) = self.map.transfer_at(p) {
+							return (
+								Status::Captured,
+								Some(Message::RemoveTransfer(a, b)),
+							);
+						}
+					}
+					(
+						EditMode::None,
+						DragState::Clicked(
+							ClickStart::Station(s) | ClickStart::Label(s, ..),
+						),
+					) => {
+						if double_click(state, ClickStart::Station(s)) {
+							return (
+								Status::Captured,
+								Some(Message::EditStation(s)),
+							);
+						}
+
+						let clicked = Selection::station(self.map.graph[s].id);
+						return (
+							Status::Captured,
+							Some(Message::Select(self.select(clicked, state))),
+						);
+					}
+					(
+						EditMode::None,
+						DragState::Clicked(ClickStart::Empty(p)),
+					) => {
+						let segment = self
+							.find_segment_at(p)
+							.map(|segment| self.map.segment(segment).id);
+
+						if let (true, Some(segment)) =
+							(double_click(state, ClickStart::Empty(p)), segment)
+						{
+							return (
+								Status::Captured,
+								Some(Message::InsertWaypoint(
+									segment,
+									render::snap(p),
+								)),
+							);
+						}
+
+						let clicked = match segment {
+							Some(segment) => Selection::segment(segment),
+							None => Selection::default(),
+						};
+						return (
+							Status::Captured,
+							Some(Message::Select(self.select(clicked, state))),
+						);
+					}
+					(
+						EditMode::None,
+						DragState::Clicked(
+							click @ ClickStart::Waypoint(segment, waypoint, _),
+						),
+					) if double_click(state, click) => {
+						return (
+							Status::Captured,
+							Some(Message::RemoveWaypoint(segment, waypoint)),
+						);
+					}
+					(EditMode::None, DragState::Selecting(a, b)) => {
+						let rect = Rectangle::new(
+							Point::new(a.x.min(b.x), a.y.min(b.y)),
+							Size::new((a.x - b.x).abs(), (a.y - b.y).abs()),
+						);
+
+						let mut selection = self.select_in(rect);
+						if state.modifiers.shift() {
+							selection.extend(self.selection.clone());
+						}
+						return (
+							Status::Captured,
+							Some(Message::Select(selection)),
+						);
+					}
+					(_, DragState::Dragging(..)) => {
+						return (Status::Captured, Some(Message::DragEnded))
+					}
 					_ => (),
 				}
 			}
 			Event::Mouse(mouse::Event::ButtonReleased(
 				mouse::Button::Middle,
 			)) => {
-				state.cache.clear();
 				state.dragging = DragState::None;
 			}
+			Event::Keyboard(keyboard::Event::ModifiersChanged(modifiers)) => {
+				state.modifiers = modifiers;
+			}
 			Event::Keyboard(keyboard::Event::KeyPressed {
 				key_code,
 				modifiers,
-			}) => {
+			}) if !self.typing && !state.unfocused => {
 				if modifiers == Modifiers::CTRL {
-					if key_code == KeyCode::Delete {
-						state.cache.clear();
-						return (Status::Captured, Some(Message::ClearMap));
+					match key_code {
+						KeyCode::Delete => {
+							return (Status::Captured, Some(Message::ClearMap));
+						}
+						KeyCode::Z => {
+							return (Status::Captured, Some(Message::Undo))
+						}
+						KeyCode::Y => {
+							return (Status::Captured, Some(Message::Redo))
+						}
+						KeyCode::O => {
+							return (Status::Captured, Some(Message::Open))
+						}
+						KeyCode::S => {
+							return (Status::Captured, Some(Message::Save))
+						}
+						KeyCode::E => {
+							return (
+								Status::Captured,
+								Some(Message::Export(ExportFormat::Svg)),
+							)
+						}
+						KeyCode::G => {
+							return (
+								Status::Captured,
+								Some(Message::ToggleExportGrid),
+							)
+						}
+						KeyCode::B => {
+							return (
+								Status::Captured,
+								Some(Message::ToggleExportBackground),
+							)
+						}
+						KeyCode::L => {
+							return (
+								Status::Captured,
+								Some(Message::PlaceLabels),
+							)
+						}
+						KeyCode::C if !self.selection.is_empty() => {
+							return (Status::Captured, Some(Message::Copy))
+						}
+						KeyCode::X if !self.selection.is_empty() => {
+							return (Status::Captured, Some(Message::Cut))
+						}
+						KeyCode::V => {
+							// Pasted at the cursor, or in the middle of the
+							// map if the cursor isn't over it
+							let at = match cursor.position() {
+								Some(p) if bounds.contains(p) => p,
+								_ => bounds.center(),
+							};
+							let at = state.camera.to_map(
+								at - (bounds.position() - Point::ORIGIN),
+							);
+
+							return (
+								Status::Captured,
+								Some(Message::Paste(at)),
+							);
+						}
+						_ => (),
+					}
+				} else if modifiers == Modifiers::CTRL | Modifiers::SHIFT {
+					match key_code {
+						KeyCode::Z => {
+							return (Status::Captured, Some(Message::Redo))
+						}
+						KeyCode::S => {
+							return (Status::Captured, Some(Message::SaveAs))
+						}
+						KeyCode::E => {
+							return (
+								Status::Captured,
+								Some(Message::Export(ExportFormat::Png)),
+							)
+						}
+						_ => (),
+					}
+				} else if modifiers == Modifiers::SHIFT
+					&& state.dragging == DragState::None
+				{
+					match key_code {
+						KeyCode::Equals => {
+							zoom(state, ZOOM_STEP, bounds, cursor);
+							return (Status::Captured, None);
+						}
+						// Home isn't a character, so it's in the same place
+						// whatever the keyboard layout
+						KeyCode::Home if !self.selection.is_empty() => {
+							let fit = self.selection_bounds();
+							state.camera = Camera::fitting(fit, bounds.size());
+							return (Status::Captured, None);
+						}
+						_ => (),
 					}
 				} else if modifiers.is_empty()
 					&& state.dragging == DragState::None
 				{
 					match key_code {
+						KeyCode::Equals
+						| KeyCode::Plus
+						| KeyCode::NumpadAdd => {
+							zoom(state, ZOOM_STEP, bounds, cursor);
+							return (Status::Captured, None);
+						}
+						KeyCode::Minus | KeyCode::NumpadSubtract => {
+							zoom(state, 1.0 / ZOOM_STEP, bounds, cursor);
+							return (Status::Captured, None);
+						}
+						KeyCode::Home => {
+							let fit = self.map.export_bounds();
+							state.camera = Camera::fitting(fit, bounds.size());
+							return (Status::Captured, None);
+						}
+						KeyCode::Delete | KeyCode::Backspace
+							if self.edit_mode == EditMode::None
+								&& !self.selection.is_empty() =>
+						{
+							return (
+								Status::Captured,
+								Some(Message::DeleteSelection),
+							)
+						}
+						KeyCode::B if self.selection.has_segments() => {
+							return (
+								Status::Captured,
+								Some(Message::CycleBendDirection),
+							)
+						}
+						KeyCode::F if self.selection.has_segments() => {
+							return (Status::Captured, Some(Message::FlipBend))
+						}
+						KeyCode::C if self.selection.has_segments() => {
+							let curved = self
+								.map
+								.selection_interpolation(self.selection)
+								.is_some_and(|i| i.is_curve());
+							return (
+								Status::Captured,
+								Some(Message::SetCurve(!curved)),
+							);
+						}
+						KeyCode::Escape if !self.selection.is_empty() => {
+							return (
+								Status::Captured,
+								Some(Message::Select(Selection::default())),
+							)
+						}
 						KeyCode::R => {
 							return (
 								Status::Captured,
@@ -488,8 +1265,8 @@ impl Program<Message> for MapView<'_> {
 impl MapView<'_> {
 	fn find_station_at(&self, p: Point) -> Option<StationIndex> {
 		self.map
-			.graph
-			.node_indices()
+			.stations_in(around(p, STATION_OUTER_SIZE))
+			.into_iter()
 			.map(|i| {
 				let d = NotNan::new(magnitude(self.map.graph[i].position - p))
 					.unwrap();
@@ -500,42 +1277,207 @@ impl MapView<'_> {
 			.map(|(i, _)| i)
 	}
 
-	fn draw_segment(
+	fn find_segment_at(&self, p: Point) -> Option<EdgeIndex<Index>> {
+		render::segment_shapes(self.map, Some(around(p, LINE_WIDTH / 2.0)))
+			.into_iter()
+			.rev()
+			.find(|(_, shape)| {
+				shape.flatten().iter().any(|points| {
+					points.windows(2).any(|w| {
+						distance_to_line(p, w[0], w[1]) < LINE_WIDTH / 2.0
+					})
+				})
+			})
+			.map(|(index, _)| index)
+	}
+
+	/// Everything entirely within a rectangle.
+	fn select_in(&self, rect: Rectangle) -> Selection {
+		let graph = &self.map.graph;
+		let mut selection = Selection::default();
+
+		for index in self.map.stations_in(rect) {
+			let station = &graph[index];
+			if rect.contains(station.position) {
+				selection.insert_station(station.id);
+			}
+		}
+
+		for (index, shape) in render::segment_shapes(self.map, Some(rect)) {
+			if shape.flatten().iter().flatten().all(|&p| rect.contains(p)) {
+				selection.insert_segment(self.map.segment(index).id);
+			}
+		}
+
+		selection
+	}
+
+	/// What should be selected after clicking on something, or nothing,
+	/// depending on whether shift is held.
+	fn select(&self, clicked: Selection, state: &ViewState) -> Selection {
+		if state.modifiers.shift() {
+			let mut selection = self.selection.clone();
+			selection.toggle(clicked);
+			selection
+		} else {
+			clicked
+		}
+	}
+
+	fn draw_selected_segments(
 		&self,
-		start: Point,
-		end: Point,
-		interpolation: Interpolation,
-		color: Color,
+		visible: Option<Rectangle>,
 		frame: &mut Frame,
 	) {
-		let line = Path::new(|b| match interpolation {
-			Interpolation::Auto(d) => {
-				b.move_to(start);
+		// Working out the segments' shapes is slow on large maps
+		if !self.selection.has_segments() {
+			return;
+		}
 
-				let mid = interpolate_auto(start, end, d);
+		let pen = Pen {
+			color: Color::from_rgb32(SELECTION_COLOR),
+			width: LINE_WIDTH + SELECTION_WIDTH * 2.0,
+			cap: Cap::Round,
+		};
 
-				arc_turn(start, mid, end, TURN_RADIUS, b);
+		for (index, shape) in render::segment_shapes(self.map, visible) {
+			if self.selection.contains_segment(self.map.segment(index).id) {
+				Surface::stroke(frame, &shape, pen);
 			}
-		});
+		}
+	}
 
-		frame.stroke(
-			&line,
-			Stroke {
-				color,
-				width: 10.0,
-				line_cap: LineCap::Round,
-				line_join: LineJoin::Round,
-				line_dash: LineDash {
-					segments: &[],
-					offset: 0,
-				},
-			},
-		);
+	fn draw_selected_stations(&self, frame: &mut Frame) {
+		if self.selection.station_count() == 0 {
+			return;
+		}
+
+		let pen = Pen {
+			color: Color::from_rgb32(SELECTION_COLOR),
+			width: SELECTION_WIDTH,
+			cap: Cap::Round,
+		};
+
+		for id in self.selection.stations() {
+			if let Some(index) = self.map.find_station(id) {
+				let (a, b) = render::station_ends(self.map, index);
+				let radius = STATION_OUTER_SIZE + SELECTION_WIDTH / 2.0;
+				Surface::stroke(frame, &Shape::capsule(a, b, radius), pen);
+			}
+		}
+	}
+
+	/// The area taken up by everything selected, with a grid square of
+	/// margin around it.
+	fn selection_bounds(&self) -> Bounds {
+		let graph = &self.map.graph;
+		let mut bounds = Bounds::default();
+
+		for (index, shape) in render::segment_shapes(self.map, None) {
+			if self.selection.contains_segment(self.map.segment(index).id) {
+				let color =
+					self.map.lines[self.map.segment(index).line as usize].color;
+				bounds.stroke(&shape, Pen::line(color));
+			}
+		}
+
+		for index in graph.node_indices() {
+			if self.selection.contains_station(graph[index].id) {
+				let (a, b) = render::station_ends(self.map, index);
+				bounds.fill(
+					&Shape::capsule(a, b, STATION_OUTER_SIZE),
+					Color::from_rgb32(SELECTION_COLOR),
+				);
+			}
+		}
+
+		bounds.padded(GRID_SIZE)
+	}
+
+	/// Finds the waypoint of a selected segment at the given point.
+	fn find_waypoint_at(&self, p: Point) -> Option<(SegmentId, usize)> {
+		self.selection.segments().find_map(|segment| {
+			self.map
+				.waypoints(segment)
+				.iter()
+				.position(|&w| magnitude(w - p) < WAYPOINT_SIZE)
+				.map(|i| (segment, i))
+		})
+	}
+
+	fn draw_waypoints(&self, frame: &mut Frame) {
+		for segment in self.selection.segments() {
+			for &waypoint in self.map.waypoints(segment) {
+				Surface::fill(
+					frame,
+					&Shape::circle(waypoint, WAYPOINT_SIZE),
+					Color::from_rgb32(SELECTION_COLOR),
+				);
+				Surface::fill(
+					frame,
+					&Shape::circle(waypoint, WAYPOINT_SIZE / 2.0),
+					Color::from_rgb32(STATION_INNER_COLOR),
+				);
+			}
+		}
+	}
+
+	fn find_label_at(&self, p: Point) -> Option<StationIndex> {
+		let graph = &self.map.graph;
+
+		self.map
+			.labels_in(around(p, 0.0))
+			.into_iter()
+			.filter(|&i| {
+				let station = &graph[i];
+				self.map.labelled(i)
+					&& station
+						.label
+						.rect(station.position, &station.name)
+						.contains(p)
+			})
+			.min()
 	}
 }
 
-fn magnitude(v: Vector) -> f32 {
-	(v.x.powi(2) + v.y.powi(2)).sqrt()
+/// Records a click, returning whether it was the second click of a double
+/// click.
+fn double_click(state: &mut ViewState, click: ClickStart) -> bool {
+	let now = Instant::now();
+
+	let double = matches!(
+		state.last_click,
+		Some((time, last))
+			if now - time < DOUBLE_CLICK_TIME
+				&& last.same_target(click, DRAG_RANGE / state.camera.zoom)
+	);
+
+	// A third click starts another double click, rather than making one with
+	// the second
+	state.last_click = (!double).then_some((now, click));
+
+	double
+}
+
+/// The square reaching `radius` from `p` in every direction.
+fn around(p: Point, radius: f32) -> Rectangle {
+	Rectangle::new(
+		p - Vector::new(radius, radius),
+		Size::new(radius * 2.0, radius * 2.0),
+	)
+}
+
+/// Zooms in by `factor` around the cursor, or around the middle of the view
+/// if the cursor isn't over it.
+fn zoom(state: &mut ViewState, factor: f32, bounds: Rectangle, cursor: Cursor) {
+	let at = match cursor.position() {
+		Some(p) if bounds.contains(p) => p,
+		_ => bounds.center(),
+	};
+
+	state.camera = state
+		.camera
+		.zoomed(factor, at - (bounds.position() - Point::ORIGIN));
 }
 
 fn min_max(values: impl IntoIterator<Item = f32>) -> (f32, f32) {
@@ -559,93 +1501,101 @@ fn min_max(values: impl IntoIterator<Item = f32>) -> (f32, f32) {
 	(min, max)
 }
 
-fn interpolate_auto(
-	start: Point,
-	end: Point,
-	direction: InterpolationDirection,
-) -> Point {
-	use InterpolationDirection::*;
-
-	let dx = end.x - start.x;
-	let dy = end.y - start.y;
-
-	let horizontal = || Point::new(end.x - dy.abs() * dx.signum(), start.y);
-	let vertical = || Point::new(start.x, end.y - dx.abs() * dy.signum());
-	let diagonal = || {
-		if dx.abs() > dy.abs() {
-			Point::new(start.x + dy.abs() * dx.signum(), end.y)
-		} else {
-			Point::new(end.x, start.y + dx.abs() * dy.signum())
-		}
-	};
-
-	match direction {
-		Horizontal => horizontal(),
-		Vertical => vertical(),
-		Diagonal => diagonal(),
-		Auto => {
-			if dx.abs() > dy.abs() {
-				horizontal()
-			} else {
-				vertical()
-			}
-		}
+impl Surface for Frame {
+	fn fill(&mut self, shape: &Shape, color: Color) {
+		Frame::fill(self, &to_path(shape), color);
 	}
-}
-
-fn arc_turn(
-	start: Point,
-	mid: Point,
-	end: Point,
-	radius: f32,
-	b: &mut Builder,
-) {
-	let start_segment_angle = f32::atan2(mid.y - start.y, mid.x - start.x);
-
-	let start_angle = start_segment_angle + PI / 2.0;
 
-	let mid_angle = start_angle - PI / 8.0;
-
-	let end_angle = start_angle - PI / 4.0;
-
-	let radius = radius.min(magnitude(end - mid));
-
-	let flipped_x = (end.x - start.x).signum();
-	let flipped_y = -(end.y - start.y).signum();
-
-	let vertical = start.x == mid.x;
-
-	let (flipped_x, flipped_y) = if vertical {
-		(-flipped_x, -flipped_y)
-	} else {
-		(flipped_x, flipped_y)
-	};
-
-	let origin = Point::new(
-		mid.x - radius * mid_angle.cos().abs() * flipped_x,
-		mid.y - radius * mid_angle.sin().abs() * flipped_y,
-	);
-
-	let arc_start = Point::new(
-		origin.x + radius * start_angle.cos().abs() * flipped_x,
-		origin.y + radius * start_angle.sin().abs() * flipped_y,
-	);
-
-	let arc_end = Point::new(
-		origin.x + radius * end_angle.cos().abs() * flipped_x,
-		origin.y + radius * end_angle.sin().abs() * flipped_y,
-	);
+	fn stroke(&mut self, shape: &Shape, pen: Pen) {
+		let (line_cap, line_join) = match pen.cap {
+			Cap::Butt => (LineCap::Butt, LineJoin::Miter),
+			Cap::Round => (LineCap::Round, LineJoin::Round),
+		};
 
-	b.line_to(arc_start);
+		Frame::stroke(
+			self,
+			&to_path(shape),
+			Stroke {
+				color: pen.color,
+				width: pen.width,
+				line_cap,
+				line_join,
+				line_dash: LineDash {
+					segments: &[],
+					offset: 0,
+				},
+			},
+		);
+	}
 
-	if !vertical && flipped_x == flipped_y || vertical && flipped_x != flipped_y
-	{
-		b.arc_to(arc_start, arc_end, radius);
-	} else {
-		b.move_to(arc_end);
-		b.arc_to(arc_end, arc_start, radius);
-		b.move_to(arc_end);
+	fn text(
+		&mut self,
+		content: &str,
+		top_left: Point,
+		size: f32,
+		color: Color,
+	) {
+		self.fill_text(Text {
+			content: content.to_owned(),
+			position: top_left,
+			color,
+			size,
+			font: Font::External {
+				name: font::NAME,
+				bytes: font::BYTES,
+			},
+			horizontal_alignment: Horizontal::Left,
+			vertical_alignment: Vertical::Top,
+		});
 	}
+}
 
-	b.line_to(end);
+fn to_path(shape: &Shape) -> Path {
+	Path::new(|b| {
+		let mut current = Point::ORIGIN;
+		for command in shape.commands() {
+			match *command {
+				PathCommand::MoveTo(p) => {
+					b.move_to(p);
+					current = p;
+				}
+				PathCommand::LineTo(p) => {
+					b.line_to(p);
+					current = p;
+				}
+				PathCommand::ArcTo {
+					to,
+					radius,
+					clockwise,
+				} => {
+					// iced's own arcs only go counterclockwise
+					match Arc::between(current, to, radius, clockwise) {
+						Some(arc) => {
+							for [a, c, to] in arc.cubics() {
+								b.bezier_curve_to(a, c, to);
+							}
+						}
+						None => b.line_to(to),
+					}
+					current = to;
+				}
+				PathCommand::QuadTo { control, to } => {
+					b.quadratic_curve_to(control, to);
+					current = to;
+				}
+				PathCommand::CubicTo {
+					control_a,
+					control_b,
+					to,
+				} => {
+					b.bezier_curve_to(control_a, control_b, to);
+					current = to;
+				}
+				PathCommand::Circle { center, radius } => {
+					b.circle(center, radius)
+				}
+				PathCommand::Close => b.close(),
+			}
+		}
+	})
 }
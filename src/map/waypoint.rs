@@ -0,0 +1,116 @@
+// Copyright (C) 2022  ThatsNoMoon
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of version 3 of the GNU Affero General Public License as published
+// by the Free Software Foundation.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU Affero General Public License
+// for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use iced::Point;
+use ordered_float::NotNan;
+
+use super::{
+	bundle::path, geometry::distance_to_line, Edit, Interpolation, Map,
+	SegmentId,
+};
+
+impl Map {
+	/// The waypoints of a segment, if it has any and still exists.
+	pub(super) fn waypoints(&self, segment: SegmentId) -> &[Point] {
+		let index = match self.find_segment(segment) {
+			Some(index) => index,
+			None => return &[],
+		};
+
+		match &self.segment(index).interpolation {
+			Interpolation::Waypoints(points) | Interpolation::Curve(points) => {
+				points
+			}
+			Interpolation::Auto(..) => &[],
+		}
+	}
+
+	/// Adds a waypoint to a segment, between the two points on its path it's
+	/// closest to. A segment that bends automatically is given a waypoint
+	/// where it bends first, so it keeps its shape, and curves stay curved.
+	/// Returns the edit that undoes this.
+	pub(crate) fn insert_waypoint(
+		&mut self,
+		segment: SegmentId,
+		at: Point,
+	) -> Edit {
+		let index = self.segment_index(segment);
+		let (start, end) = self
+			.graph
+			.edge_endpoints(index)
+			.expect("Segment index from lookup not in graph");
+
+		let (start, end) =
+			(self.graph[start].position, self.graph[end].position);
+
+		let mut segment = self.segment(index).clone();
+		if let Interpolation::Auto(..) = segment.interpolation {
+			let path = path(start, end, &segment.interpolation);
+			let corners = path[1..path.len() - 1].to_vec();
+			segment.interpolation = Interpolation::Waypoints(corners);
+		}
+		let points = segment
+			.interpolation
+			.waypoints_mut()
+			.expect("Segment has no waypoints");
+
+		let leg = [start]
+			.iter()
+			.chain(points.iter())
+			.chain([&end])
+			.copied()
+			.collect::<Vec<_>>()
+			.windows(2)
+			.map(|leg| {
+				NotNan::new(distance_to_line(at, leg[0], leg[1])).unwrap()
+			})
+			.enumerate()
+			.min_by_key(|&(_, d)| d)
+			.map_or(0, |(i, _)| i);
+
+		points.insert(leg, at);
+
+		self.replace_segment(segment)
+	}
+
+	/// Moves one of a segment's waypoints, returning the edit that undoes it.
+	pub(crate) fn move_waypoint(
+		&mut self,
+		segment: SegmentId,
+		waypoint: usize,
+		to: Point,
+	) -> Edit {
+		let mut segment = self.segment(self.segment_index(segment)).clone();
+		if let Some(points) = segment.interpolation.waypoints_mut() {
+			points[waypoint] = to;
+		}
+
+		self.replace_segment(segment)
+	}
+
+	/// Removes one of a segment's waypoints, returning the edit that undoes
+	/// it.
+	pub(crate) fn remove_waypoint(
+		&mut self,
+		segment: SegmentId,
+		waypoint: usize,
+	) -> Edit {
+		let mut segment = self.segment(self.segment_index(segment)).clone();
+		if let Some(points) = segment.interpolation.waypoints_mut() {
+			points.remove(waypoint);
+		}
+
+		self.replace_segment(segment)
+	}
+}
@@ -0,0 +1,464 @@
+// Copyright (C) 2022  ThatsNoMoon
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of version 3 of the GNU Affero General Public License as published
+// by the Free Software Foundation.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU Affero General Public License
+// for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! The panel beside the map for managing lines and how the map is drawn.
+
+use std::fmt;
+
+use iced::{
+	pure::{
+		button, checkbox, column, container, horizontal_rule, horizontal_space,
+		pick_list, row, scrollable, slider, text, text_input, Element,
+	},
+	Alignment, Background, Color, Length,
+};
+
+use crate::{
+	color::ColorExt,
+	map::{
+		InterpolationDirection, Line, LineIndex, Map, Selection,
+		MAX_TURN_RADIUS, PALETTE,
+	},
+	Message,
+};
+
+const WIDTH: u16 = 280;
+
+const SWATCH_SIZE: u16 = 16;
+
+/// How many palette colors are shown in each row.
+const PALETTE_COLUMNS: usize = 6;
+
+/// Panel state that isn't part of the map, reset whenever a different line is
+/// selected.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct LinePanel {
+	/// What's been typed into the hex color field, if it's been changed
+	/// since the line's color last was.
+	pub(crate) hex: Option<String>,
+	/// The line a deleted line's segments are moved to, or `None` to delete
+	/// them with it.
+	pub(crate) reassign: Option<LineIndex>,
+}
+
+pub(crate) fn view<'a>(
+	map: &'a Map,
+	selected: LineIndex,
+	panel: &'a LinePanel,
+	selection: &Selection,
+) -> Element<'a, Message> {
+	let lines = map.lines().iter().enumerate().fold(
+		column().spacing(2),
+		|list, (index, line)| {
+			let index = index as LineIndex;
+			let entry = row()
+				.spacing(10)
+				.align_items(Alignment::Center)
+				.push(swatch(line.color()))
+				.push(text(label(index, line)));
+
+			list.push(
+				button(entry)
+					.width(Length::Fill)
+					.padding(5)
+					.style(Entry(index == selected))
+					.on_press(Message::SwitchLine(index)),
+			)
+		},
+	);
+
+	let mut content = column()
+		.spacing(10)
+		.push(text("Lines").size(24))
+		.push(lines)
+		.push(button("Add line").on_press(Message::AddLine))
+		.push(horizontal_rule(10))
+		.push(editor(map, selected, panel, selection));
+
+	if selection.station_count() > 0 {
+		content = content
+			.push(horizontal_rule(10))
+			.push(stations(map, selection));
+	}
+
+	if selection.has_segments() {
+		content = content
+			.push(horizontal_rule(10))
+			.push(segments(map, selection));
+	}
+
+	content = content
+		.push(horizontal_rule(10))
+		.push(text("Map").size(24))
+		.push(turn_radius(map.style().turn_radius, Message::SetTurnRadius));
+
+	container(scrollable(content))
+		.width(Length::Units(WIDTH))
+		.height(Length::Fill)
+		.padding(10)
+		.into()
+}
+
+/// Controls for the selected line.
+fn editor<'a>(
+	map: &'a Map,
+	index: LineIndex,
+	panel: &'a LinePanel,
+	selection: &Selection,
+) -> Element<'a, Message> {
+	let line = &map.lines()[index as usize];
+	let color = line.color();
+
+	let hex = panel
+		.hex
+		.clone()
+		.unwrap_or_else(|| format!("#{:06x}", color.to_rgb32()));
+
+	let channel = |name, value: f32, set: fn(Color, f32) -> Color| {
+		row()
+			.spacing(10)
+			.align_items(Alignment::Center)
+			.push(text(name).width(Length::Units(15)))
+			.push(slider(0..=255, (value * 255.0).round() as u8, move |v| {
+				Message::LineColorChanged(index, set(color, v as f32 / 255.0))
+			}))
+	};
+
+	let palette = PALETTE.chunks(PALETTE_COLUMNS).fold(
+		column().spacing(5),
+		|palette, colors| {
+			palette.push(colors.iter().fold(row().spacing(5), |row, &c| {
+				let c = Color::from_rgb32(c);
+				row.push(
+					button(swatch(c))
+						.padding(3)
+						.style(Entry(c == color))
+						.on_press(Message::LineColorChanged(index, c)),
+				)
+			}))
+		},
+	);
+
+	let last = map.lines().len() as LineIndex - 1;
+
+	let order = row()
+		.spacing(10)
+		.align_items(Alignment::Center)
+		.push(text("Order"))
+		.push(move_button("Up", index, index.checked_sub(1)))
+		.push(move_button(
+			"Down",
+			index,
+			(index < last).then(|| index + 1),
+		));
+
+	let mut editor = column()
+		.spacing(10)
+		.push(text("Name"))
+		.push(
+			text_input("Line name", line.name(), move |name| {
+				Message::LineNameChanged(index, name)
+			})
+			.padding(5),
+		)
+		.push(text("Code"))
+		.push(
+			text_input("Short name, e.g. A", line.code(), move |code| {
+				Message::LineCodeChanged(index, code)
+			})
+			.padding(5),
+		)
+		.push(
+			row()
+				.spacing(10)
+				.align_items(Alignment::Center)
+				.push(text("Color"))
+				.push(swatch(color))
+				.push(
+					text_input("#rrggbb", &hex, move |hex| {
+						Message::LineHexChanged(index, hex)
+					})
+					.padding(5),
+				),
+		)
+		.push(channel("R", color.r, |c, r| Color { r, ..c }))
+		.push(channel("G", color.g, |c, g| Color { g, ..c }))
+		.push(channel("B", color.b, |c, b| Color { b, ..c }))
+		.push(palette)
+		.push(order);
+
+	if !map.services(index).is_empty() {
+		editor = editor.push(services(map, index));
+	}
+
+	if selection.has_segments() {
+		editor = editor.push(
+			button("Move selected segments here")
+				.on_press(Message::RecolorSelection),
+		);
+	}
+
+	// Segments can only be moved to another line when there is one
+	if map.line_segments(index) > 0 && last > 0 {
+		let choices: Vec<_> = std::iter::once(None)
+			.chain((0..=last).filter(|&l| l != index).map(Some))
+			.map(|line| Choice::new(map, line))
+			.collect();
+		let chosen = Choice::new(map, panel.reassign);
+
+		editor = editor.push(text("Segments on deleted line")).push(
+			pick_list(choices, Some(chosen), |choice| {
+				Message::ReassignPicked(choice.line)
+			})
+			.width(Length::Fill),
+		);
+	}
+
+	let mut delete = button("Delete line");
+	if last > 0 {
+		delete = delete.on_press(Message::RemoveLine(index, panel.reassign));
+	}
+
+	editor
+		.push(row().push(horizontal_space(Length::Fill)).push(delete))
+		.into()
+}
+
+/// Where the selected line runs.
+fn services<'a>(map: &Map, line: LineIndex) -> Element<'a, Message> {
+	let name = |id| {
+		map.find_station(id)
+			.map(|index| map.station(index).name())
+			.filter(|name| !name.is_empty())
+			.unwrap_or("Unnamed station")
+			.to_owned()
+	};
+
+	let termini: Vec<_> = map.termini(line).into_iter().map(name).collect();
+	let stations = map.line_stations(line).len();
+
+	let mut summary = format!("{stations} stations");
+	if !termini.is_empty() {
+		summary = format!("{summary}, ending at {}", termini.join(", "));
+	}
+
+	let list = map.services(line).iter().fold(
+		column().spacing(5).push(text(summary)),
+		|list, service| {
+			let stations = service.stations();
+			let description = if service.is_loop() {
+				format!("Loop from {}", name(stations[0]))
+			} else {
+				format!(
+					"{} to {}",
+					name(stations[0]),
+					name(stations[stations.len() - 1])
+				)
+			};
+
+			list.push(text(format!("• {description}")))
+		},
+	);
+
+	column()
+		.spacing(10)
+		.push(
+			row()
+				.spacing(10)
+				.align_items(Alignment::Center)
+				.push(text("Services"))
+				.push(horizontal_space(Length::Fill))
+				.push(
+					button("Reverse").on_press(Message::ReverseServices(line)),
+				),
+		)
+		.push(list)
+		.into()
+}
+
+/// Controls for joining the selected stations into a complex.
+fn stations<'a>(map: &Map, selection: &Selection) -> Element<'a, Message> {
+	let mut join = button("Join with transfers");
+	if selection.station_count() > 1 {
+		join = join.on_press(Message::JoinStations);
+	}
+
+	let mut separate = button("Separate");
+	if map.selection_has_transfers(selection) {
+		separate = separate.on_press(Message::SeparateStations);
+	}
+
+	column()
+		.spacing(10)
+		.push(text("Selected stations").size(24))
+		.push(row().spacing(10).push(join).push(separate))
+		.into()
+}
+
+/// Controls for the selected segments.
+fn segments<'a>(map: &Map, selection: &Selection) -> Element<'a, Message> {
+	let own_radius = map.selection_turn_radius(selection);
+	let radius = own_radius.flatten().unwrap_or(map.style().turn_radius);
+
+	let mut reset = button("Default");
+	if own_radius != Some(None) {
+		reset = reset.on_press(Message::SetSegmentTurnRadius(None));
+	}
+
+	let interpolation = map.selection_interpolation(selection);
+	let direction = interpolation.as_ref().and_then(|i| i.direction());
+	let curved = interpolation.is_some_and(|i| i.is_curve());
+
+	column()
+		.spacing(10)
+		.push(text("Selected segments").size(24))
+		.push(
+			row()
+				.spacing(10)
+				.align_items(Alignment::Center)
+				.push(text("Bend"))
+				.push(
+					pick_list(
+						&InterpolationDirection::ALL[..],
+						direction,
+						Message::SetBendDirection,
+					)
+					.width(Length::Fill),
+				)
+				.push(button("Flip").on_press(Message::FlipBend)),
+		)
+		.push(checkbox("Curved", curved, Message::SetCurve))
+		.push(button("Select along their lines").on_press(Message::SelectRuns))
+		.push(
+			row()
+				.spacing(10)
+				.align_items(Alignment::Center)
+				.push(turn_radius(radius, |r| {
+					Message::SetSegmentTurnRadius(Some(r))
+				}))
+				.push(reset),
+		)
+		.into()
+}
+
+/// A slider for how sharply segments turn at their corners.
+fn turn_radius<'a>(
+	radius: f32,
+	on_change: impl Fn(f32) -> Message + 'a,
+) -> Element<'a, Message> {
+	row()
+		.spacing(10)
+		.align_items(Alignment::Center)
+		.push(text("Corners"))
+		.push(slider(0.0..=MAX_TURN_RADIUS, radius, on_change).step(5.0))
+		.push(text(format!("{radius:.0}")).width(Length::Units(30)))
+		.into()
+}
+
+fn move_button<'a>(
+	name: &str,
+	from: LineIndex,
+	to: Option<LineIndex>,
+) -> Element<'a, Message> {
+	let button = button(text(name));
+	match to {
+		Some(to) => button.on_press(Message::MoveLine(from, to)).into(),
+		None => button.into(),
+	}
+}
+
+/// What a line is called in the panel.
+fn label(index: LineIndex, line: &Line) -> String {
+	match (line.code(), line.name()) {
+		("", "") => format!("Line {}", index + 1),
+		(code, "") => code.to_owned(),
+		("", name) => name.to_owned(),
+		(code, name) => format!("{code} {name}"),
+	}
+}
+
+fn swatch<'a>(color: Color) -> Element<'a, Message> {
+	container(horizontal_space(Length::Units(SWATCH_SIZE)))
+		.width(Length::Units(SWATCH_SIZE))
+		.height(Length::Units(SWATCH_SIZE))
+		.style(Swatch(color))
+		.into()
+}
+
+/// Where a deleted line's segments can go.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Choice {
+	line: Option<LineIndex>,
+	label: String,
+}
+
+impl Choice {
+	fn new(map: &Map, line: Option<LineIndex>) -> Self {
+		let label = match line {
+			Some(l) => {
+				format!("Move to {}", label(l, &map.lines()[l as usize]))
+			}
+			None => "Delete them".to_owned(),
+		};
+
+		Self { line, label }
+	}
+}
+
+impl fmt::Display for Choice {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(&self.label)
+	}
+}
+
+struct Swatch(Color);
+
+impl iced::container::StyleSheet for Swatch {
+	fn style(&self) -> iced::container::Style {
+		iced::container::Style {
+			background: Some(Background::Color(self.0)),
+			border_radius: 3.0,
+			border_width: 1.0,
+			border_color: Color::from_rgb(0.3, 0.3, 0.3),
+			..iced::container::Style::default()
+		}
+	}
+}
+
+/// A button in a list, highlighted if it's selected.
+struct Entry(bool);
+
+impl iced::button::StyleSheet for Entry {
+	fn active(&self) -> iced::button::Style {
+		iced::button::Style {
+			background: self
+				.0
+				.then(|| Background::Color(Color::from_rgb(0.8, 0.87, 1.0))),
+			border_radius: 3.0,
+			text_color: Color::BLACK,
+			..iced::button::Style::default()
+		}
+	}
+
+	fn hovered(&self) -> iced::button::Style {
+		let active = self.active();
+
+		iced::button::Style {
+			background: active
+				.background
+				.or(Some(Background::Color(Color::from_rgb(0.92, 0.92, 0.92)))),
+			..active
+		}
+	}
+}